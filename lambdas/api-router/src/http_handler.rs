@@ -0,0 +1,69 @@
+use crate::routes::{match_route, Handler, RouteMatch};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::http::header::ALLOW;
+use lambda_runtime::LambdaEvent;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use tracing::info;
+
+pub(crate) async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let (mut request, context) = event.into_parts();
+
+    let method = request.http_method.as_str().to_string();
+    let route_match = match_route(
+        &method,
+        request.resource.as_deref(),
+        request.path.as_deref(),
+    );
+
+    info!(
+        "Routing {} {:?} (resource {:?}): {:?}",
+        method, request.path, request.resource, route_match
+    );
+
+    let route = match route_match {
+        RouteMatch::Found {
+            route,
+            path_parameters,
+        } => {
+            if let Some(params) = path_parameters {
+                request.path_parameters = params;
+            }
+            route
+        }
+        RouteMatch::MethodNotAllowed(allowed) => {
+            let mut response = error_response(405, "Method not allowed");
+            response.headers.insert(ALLOW, allowed.join(", ").parse()?);
+            return Ok(response);
+        }
+        RouteMatch::NotFound => return Ok(error_response(404, "Route not found")),
+    };
+
+    if !route.scopes.is_empty() {
+        if let Err(err) = require_any_scope(&request, route.scopes) {
+            return Ok(err.into_response());
+        }
+    }
+
+    let event = LambdaEvent::new(request, context);
+
+    match route.handler {
+        Handler::CreateContent => create_content::http_handler::function_handler(ctx, event).await,
+        Handler::CreatePost => create_post::http_handler::function_handler(ctx, event).await,
+        Handler::DeletePost => delete_post::http_handler::function_handler(ctx, event).await,
+        Handler::GetContent => get_content::http_handler::function_handler(ctx, event).await,
+        Handler::GetPost => get_post::http_handler::function_handler(ctx, event).await,
+        Handler::GetPosts => get_posts::http_handler::function_handler(ctx, event).await,
+        Handler::GetAuthor => get_author::http_handler::function_handler(ctx, event).await,
+        Handler::UpdateAuthor => update_author::http_handler::function_handler(ctx, event).await,
+        Handler::CreateUpload => create_upload::http_handler::function_handler(ctx, event).await,
+        Handler::FinalizeUpload => {
+            finalize_upload::http_handler::function_handler(ctx, event).await
+        }
+    }
+}
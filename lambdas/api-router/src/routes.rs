@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Handler {
+    CreateContent,
+    CreatePost,
+    DeletePost,
+    GetContent,
+    GetPost,
+    GetPosts,
+    GetAuthor,
+    UpdateAuthor,
+    CreateUpload,
+    FinalizeUpload,
+}
+
+#[derive(Debug)]
+pub(crate) struct Route {
+    pub method: &'static str,
+    pub resource: &'static str,
+    pub handler: Handler,
+    /// Any one of these scopes grants access. Empty means the route is public.
+    pub scopes: &'static [&'static str],
+}
+
+pub(crate) const ROUTES: &[Route] = &[
+    Route {
+        method: "GET",
+        resource: "/posts",
+        handler: Handler::GetPosts,
+        scopes: &[],
+    },
+    Route {
+        method: "GET",
+        resource: "/post/{id}",
+        handler: Handler::GetPost,
+        scopes: &[],
+    },
+    Route {
+        method: "POST",
+        resource: "/post",
+        handler: Handler::CreatePost,
+        scopes: &["author.write", "admin.write"],
+    },
+    Route {
+        method: "DELETE",
+        resource: "/post",
+        handler: Handler::DeletePost,
+        scopes: &["author.delete", "admin.delete"],
+    },
+    Route {
+        method: "GET",
+        resource: "/content/{id}",
+        handler: Handler::GetContent,
+        scopes: &[],
+    },
+    Route {
+        method: "POST",
+        resource: "/content",
+        handler: Handler::CreateContent,
+        scopes: &["author.write", "admin.write"],
+    },
+    Route {
+        method: "POST",
+        resource: "/content/upload",
+        handler: Handler::CreateUpload,
+        scopes: &["author.write", "admin.write"],
+    },
+    Route {
+        method: "POST",
+        resource: "/content/{id}/finalize",
+        handler: Handler::FinalizeUpload,
+        scopes: &["author.write", "admin.write"],
+    },
+    Route {
+        method: "GET",
+        resource: "/author/{id}",
+        handler: Handler::GetAuthor,
+        scopes: &[],
+    },
+    Route {
+        method: "PUT",
+        resource: "/author",
+        handler: Handler::UpdateAuthor,
+        scopes: &["author.write", "admin.write"],
+    },
+];
+
+#[derive(Debug)]
+pub(crate) enum RouteMatch {
+    Found {
+        route: &'static Route,
+        path_parameters: Option<HashMap<String, String>>,
+    },
+    MethodNotAllowed(Vec<&'static str>),
+    NotFound,
+}
+
+/// Matches a request against the route table.
+///
+/// API Gateway already resolves `resource` to one of our templates when each route is
+/// configured explicitly, in which case its path parameters are kept as-is. Behind a
+/// `/{proxy+}` resource we fall back to matching the raw `path` and extract the
+/// parameters ourselves.
+pub(crate) fn match_route(method: &str, resource: Option<&str>, path: Option<&str>) -> RouteMatch {
+    let mut allowed: Vec<&'static str> = Vec::new();
+
+    for route in ROUTES {
+        let path_parameters = if resource == Some(route.resource) {
+            None
+        } else if let Some(params) = path.and_then(|p| match_template(route.resource, p)) {
+            Some(params)
+        } else {
+            continue;
+        };
+
+        if route.method.eq_ignore_ascii_case(method) {
+            return RouteMatch::Found {
+                route,
+                path_parameters,
+            };
+        }
+        allowed.push(route.method);
+    }
+
+    if allowed.is_empty() {
+        RouteMatch::NotFound
+    } else {
+        RouteMatch::MethodNotAllowed(allowed)
+    }
+}
+
+fn match_template(template: &str, path: &str) -> Option<HashMap<String, String>> {
+    let template_segments: Vec<&str> = template.trim_matches('/').split('/').collect();
+    let path_segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    if template_segments.len() != path_segments.len() {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    for (template_segment, path_segment) in template_segments.iter().zip(path_segments) {
+        match template_segment
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+        {
+            Some(name) if !path_segment.is_empty() => {
+                params.insert(name.to_string(), path_segment.to_string());
+            }
+            Some(_) => return None,
+            None if *template_segment == path_segment => {}
+            None => return None,
+        }
+    }
+
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(route_match: RouteMatch) -> (Handler, Option<HashMap<String, String>>) {
+        match route_match {
+            RouteMatch::Found {
+                route,
+                path_parameters,
+            } => (route.handler, path_parameters),
+            other => panic!("expected a route, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resource_match_keeps_gateway_path_parameters() {
+        let (handler, params) = found(match_route(
+            "GET",
+            Some("/post/{id}"),
+            Some("/post/hello-world"),
+        ));
+        assert_eq!(handler, Handler::GetPost);
+        assert_eq!(params, None);
+    }
+
+    #[test]
+    fn proxy_resource_matches_the_raw_path() {
+        let (handler, params) = found(match_route(
+            "GET",
+            Some("/{proxy+}"),
+            Some("/post/hello-world"),
+        ));
+        assert_eq!(handler, Handler::GetPost);
+        assert_eq!(
+            params,
+            Some(HashMap::from([(
+                "id".to_string(),
+                "hello-world".to_string()
+            )]))
+        );
+    }
+
+    #[test]
+    fn methods_match_case_insensitively() {
+        let (handler, _) = found(match_route("put", None, Some("/author")));
+        assert_eq!(handler, Handler::UpdateAuthor);
+    }
+
+    #[test]
+    fn upload_is_not_taken_for_a_content_id() {
+        let (handler, params) = found(match_route("POST", None, Some("/content/upload")));
+        assert_eq!(handler, Handler::CreateUpload);
+        assert_eq!(params, Some(HashMap::new()));
+
+        let (handler, params) = found(match_route("GET", None, Some("/content/upload")));
+        assert_eq!(handler, Handler::GetContent);
+        assert_eq!(
+            params,
+            Some(HashMap::from([("id".to_string(), "upload".to_string())]))
+        );
+    }
+
+    #[test]
+    fn finalize_extracts_the_content_id() {
+        let (handler, params) = found(match_route("POST", None, Some("/content/abc/finalize")));
+        assert_eq!(handler, Handler::FinalizeUpload);
+        assert_eq!(
+            params,
+            Some(HashMap::from([("id".to_string(), "abc".to_string())]))
+        );
+    }
+
+    #[test]
+    fn wrong_method_lists_the_allowed_ones() {
+        match match_route("PATCH", None, Some("/post")) {
+            RouteMatch::MethodNotAllowed(allowed) => assert_eq!(allowed, vec!["POST", "DELETE"]),
+            other => panic!("expected 405, got {:?}", other),
+        }
+        match match_route("DELETE", Some("/content/{id}"), Some("/content/abc")) {
+            RouteMatch::MethodNotAllowed(allowed) => assert_eq!(allowed, vec!["GET"]),
+            other => panic!("expected 405, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_paths_and_empty_parameters_are_not_found() {
+        for path in ["/nope", "/post/a/b", "/posts/extra", "/content//finalize"] {
+            assert!(
+                matches!(match_route("GET", None, Some(path)), RouteMatch::NotFound),
+                "{}",
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use chrono::{DateTime, Utc};
+use lambda_runtime::LambdaEvent;
+use serde::Serialize;
+use serde_json::Value;
+use shared::context::AppContext;
+use shared::db::{delete_content, scan_avatar_content_ids, scan_contents, scan_post_content_keys};
+use shared::markdown::content_references;
+use shared::media::UPLOAD_PREFIX;
+use shared::models::{Content, ContentStatus};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// What one run found, by content id or object key. In a dry run nothing was deleted.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct GcReport {
+    #[serde(rename = "dryRun")]
+    dry_run: bool,
+    /// Ready content that no post, author profile or referenced markdown points at.
+    unreferenced: Vec<String>,
+    /// Presigned uploads that were never finalized.
+    #[serde(rename = "staleUploads")]
+    stale_uploads: Vec<String>,
+    /// Objects whose content item does not exist, and staged uploads of finalized content.
+    #[serde(rename = "orphanedObjects")]
+    orphaned_objects: Vec<String>,
+    /// Unreferenced content items whose object does not exist.
+    #[serde(rename = "missingObjects")]
+    missing_objects: Vec<String>,
+    /// Referenced content items whose object does not exist. They are kept, since
+    /// deleting them would not fix whatever points at them.
+    #[serde(rename = "brokenReferences")]
+    broken_references: Vec<String>,
+}
+
+/// The report for one run, with what it would delete.
+#[derive(Debug)]
+struct GcPlan<'a> {
+    report: GcReport,
+    contents: Vec<&'a Content>,
+    orphaned_keys: Vec<&'a str>,
+}
+
+/// The content id an object belongs to: `content/<id>.<ext>`, `uploads/<id>.<ext>` or
+/// `variants/<id>/<name>`.
+fn object_content_id(key: &str) -> Option<&str> {
+    let id = match key.strip_prefix("variants/") {
+        Some(rest) => rest.split_once('/')?.0,
+        None => {
+            key.strip_prefix("content/")
+                .or_else(|| key.strip_prefix(UPLOAD_PREFIX))?
+                .rsplit_once('.')?
+                .0
+        }
+    };
+    (!id.is_empty() && !id.contains('/')).then_some(id)
+}
+
+/// Whether an RFC 3339 timestamp is before `cutoff`. Unreadable timestamps are treated
+/// as recent, so their content is kept.
+fn created_before(created_at: &str, cutoff: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(created_at).is_ok_and(|created_at| created_at < cutoff)
+}
+
+/// Whether content was created, and last uploaded again, before `cutoff`.
+fn expired(content: &Content, cutoff: DateTime<Utc>) -> bool {
+    created_before(&content.created_at, cutoff)
+        && content
+            .last_uploaded_at
+            .as_deref()
+            .is_none_or(|uploaded_at| created_before(uploaded_at, cutoff))
+}
+
+/// Decides what a run deletes from the content items, the bucket's objects with their
+/// last-modified times and the referenced content ids. Nothing newer than `cutoff` and
+/// nothing referenced is ever picked.
+fn plan<'a>(
+    contents: &'a [Content],
+    objects: &'a HashMap<String, i64>,
+    referenced: &HashSet<String>,
+    bucket: &str,
+    cutoff: DateTime<Utc>,
+) -> GcPlan<'a> {
+    let mut report = GcReport::default();
+    let mut collect: Vec<&Content> = Vec::new();
+    let mut known_ids = HashSet::new();
+    let mut pending_ids = HashSet::new();
+
+    for content in contents {
+        let Some(content_id) = content.pk.strip_prefix("CONTENT#") else {
+            continue;
+        };
+        known_ids.insert(content_id);
+        let expired = expired(content, cutoff);
+
+        match content.status {
+            ContentStatus::Pending => {
+                pending_ids.insert(content_id);
+                if expired {
+                    report.stale_uploads.push(content_id.to_string());
+                    collect.push(content);
+                }
+            }
+            ContentStatus::Ready => {
+                // Content stored in another bucket is not this job's to judge.
+                let Some(key) = content.s3_key(bucket) else {
+                    continue;
+                };
+                if referenced.contains(content_id) {
+                    if !objects.contains_key(key) {
+                        report.broken_references.push(content_id.to_string());
+                    }
+                } else if expired {
+                    if objects.contains_key(key) {
+                        report.unreferenced.push(content_id.to_string());
+                    } else {
+                        report.missing_objects.push(content_id.to_string());
+                    }
+                    collect.push(content);
+                }
+            }
+        }
+    }
+
+    // create-content stores the object before the item, so only old objects count. A
+    // staged upload is only needed until its content is finalized.
+    let mut orphaned_keys: Vec<&str> = objects
+        .iter()
+        .filter(|(key, modified)| {
+            let needed = if key.starts_with(UPLOAD_PREFIX) {
+                &pending_ids
+            } else {
+                &known_ids
+            };
+            **modified < cutoff.timestamp()
+                && object_content_id(key).is_some_and(|id| !needed.contains(id))
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+    orphaned_keys.sort_unstable();
+    report.orphaned_objects = orphaned_keys.iter().map(|key| key.to_string()).collect();
+
+    GcPlan {
+        report,
+        contents: collect,
+        orphaned_keys,
+    }
+}
+
+/// Runs on a schedule. Deletes only when `GC_DELETE` is set, and an event with
+/// `"dryRun": true` forces a dry run, for checking the report by hand.
+pub(crate) async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<Value>,
+) -> Result<GcReport, Error> {
+    let gc = &ctx.config.gc;
+    let dry_run = !gc.delete
+        || event
+            .payload
+            .get("dryRun")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+    let cutoff = Utc::now() - chrono::Duration::seconds(gc.grace_period as i64);
+
+    let table_name = ctx.config.content_table()?;
+    let bucket = ctx.config.content_bucket()?;
+
+    let contents = scan_contents(&ctx.dynamodb, table_name).await?;
+    let objects = list_objects(ctx, bucket).await?;
+    let referenced = referenced_content(ctx, bucket, &contents).await?;
+
+    let GcPlan {
+        mut report,
+        contents: collect,
+        orphaned_keys,
+    } = plan(&contents, &objects, &referenced, bucket, cutoff);
+    report.dry_run = dry_run;
+
+    info!(
+        "Found {} unreferenced, {} stale uploads, {} orphaned objects, {} missing objects, {} broken references",
+        report.unreferenced.len(),
+        report.stale_uploads.len(),
+        report.orphaned_objects.len(),
+        report.missing_objects.len(),
+        report.broken_references.len()
+    );
+    if dry_run {
+        info!("Dry run: {:?}", report);
+        return Ok(report);
+    }
+
+    for content in collect {
+        // The item goes first: if it was uploaded again since the scan, it is kept
+        // along with its objects.
+        if !delete_content(&ctx.dynamodb, table_name, content).await? {
+            info!("Kept {}, it was uploaded again", content.pk);
+            continue;
+        }
+        let mut keys: Vec<&str> = content.variants.iter().map(|v| v.key.as_str()).collect();
+        keys.extend(content.s3_key(bucket));
+        for key in keys.into_iter().filter(|key| objects.contains_key(*key)) {
+            ctx.s3
+                .delete_object()
+                .bucket(bucket)
+                .key(key)
+                .send()
+                .await?;
+        }
+        info!("Deleted {}", content.pk);
+    }
+    for key in orphaned_keys {
+        ctx.s3
+            .delete_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+        info!("Deleted object {}", key);
+    }
+
+    Ok(report)
+}
+
+/// Every object in the bucket, with its last-modified time in epoch seconds.
+async fn list_objects(ctx: &AppContext, bucket: &str) -> Result<HashMap<String, i64>, Error> {
+    let mut objects = HashMap::new();
+    let mut pages = ctx
+        .s3
+        .list_objects_v2()
+        .bucket(bucket)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        for object in page?.contents.unwrap_or_default() {
+            if let (Some(key), Some(modified)) = (object.key, object.last_modified) {
+                objects.insert(key, modified.secs());
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// Ids of content that posts and author profiles refer to, plus content that referenced
+/// markdown embeds or links to.
+///
+/// Anything missing from this set may be deleted, so any failure to read a reference
+/// fails the whole run rather than leaving it out.
+async fn referenced_content(
+    ctx: &AppContext,
+    bucket: &str,
+    contents: &[Content],
+) -> Result<HashSet<String>, Error> {
+    let posts_table = ctx.config.posts_table()?;
+    let mut referenced: HashSet<String> = scan_post_content_keys(&ctx.dynamodb, posts_table)
+        .await?
+        .into_iter()
+        .collect();
+    referenced.extend(scan_avatar_content_ids(&ctx.dynamodb, posts_table).await?);
+
+    let markdown = contents.iter().filter(|content| {
+        content.is_markdown()
+            && content.status == ContentStatus::Ready
+            && content
+                .pk
+                .strip_prefix("CONTENT#")
+                .is_some_and(|id| referenced.contains(id))
+    });
+    let mut embedded = Vec::new();
+    for content in markdown {
+        let key = content
+            .s3_key(bucket)
+            .ok_or_else(|| format!("{} is stored outside the content bucket", content.pk))?;
+        let object = ctx
+            .s3
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| format!("Could not read {} for references: {}", key, e))?;
+        let bytes = object.body.collect().await?.into_bytes();
+        embedded.extend(content_references(&String::from_utf8_lossy(&bytes)));
+    }
+    referenced.extend(embedded);
+
+    Ok(referenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const BUCKET: &str = "blog";
+
+    fn content(id: &str, status: ContentStatus, created_at: DateTime<Utc>) -> Content {
+        Content {
+            pk: format!("CONTENT#{}", id),
+            sk: "META".to_string(),
+            content_type: "image/png".to_string(),
+            location: format!("s3://{}/content/{}.png", BUCKET, id),
+            created_at: created_at.to_rfc3339(),
+            last_uploaded_at: None,
+            author_id: "author".to_string(),
+            status,
+            size: None,
+            variants: Vec::new(),
+            sha256: None,
+        }
+    }
+
+    #[test]
+    fn object_content_id_reads_every_layout() {
+        assert_eq!(object_content_id("content/abc.png"), Some("abc"));
+        assert_eq!(object_content_id("uploads/abc.tar.gz"), Some("abc.tar"));
+        assert_eq!(object_content_id("variants/abc/w640.webp"), Some("abc"));
+        for key in [
+            "content/abc",
+            "content/.png",
+            "content/a/b.png",
+            "variants/abc",
+            "variants//w640.webp",
+            "other/abc.png",
+        ] {
+            assert_eq!(object_content_id(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn created_before_keeps_unreadable_timestamps() {
+        let cutoff = Utc::now();
+        let earlier = (cutoff - Duration::hours(1)).to_rfc3339();
+        let later = (cutoff + Duration::hours(1)).to_rfc3339();
+        assert!(created_before(&earlier, cutoff));
+        assert!(!created_before(&later, cutoff));
+        assert!(!created_before("yesterday", cutoff));
+        assert!(!created_before("", cutoff));
+    }
+
+    #[test]
+    fn repeat_upload_restarts_the_grace_period() {
+        let cutoff = Utc::now();
+        let mut item = content("abc", ContentStatus::Ready, cutoff - Duration::days(30));
+        assert!(expired(&item, cutoff));
+
+        item.last_uploaded_at = Some((cutoff + Duration::minutes(1)).to_rfc3339());
+        assert!(!expired(&item, cutoff));
+
+        item.last_uploaded_at = Some((cutoff - Duration::days(1)).to_rfc3339());
+        assert!(expired(&item, cutoff));
+    }
+
+    #[test]
+    fn plan_classifies_content_and_objects() {
+        let now = Utc::now();
+        let cutoff = now - Duration::days(7);
+        let old = now - Duration::days(30);
+        let mut reuploaded = content("reuploaded", ContentStatus::Ready, old);
+        reuploaded.last_uploaded_at = Some(now.to_rfc3339());
+
+        let contents = vec![
+            content("unreferenced", ContentStatus::Ready, old),
+            content("referenced", ContentStatus::Ready, old),
+            content("recent", ContentStatus::Ready, now),
+            reuploaded,
+            content("missing", ContentStatus::Ready, old),
+            content("missing-recent", ContentStatus::Ready, now),
+            content("broken", ContentStatus::Ready, old),
+            content("stale", ContentStatus::Pending, old),
+            content("pending", ContentStatus::Pending, now),
+        ];
+        let old_object = old.timestamp();
+        let objects: HashMap<String, i64> = [
+            ("content/unreferenced.png", old_object),
+            ("variants/unreferenced/w640.webp", old_object),
+            ("uploads/unreferenced.png", old_object),
+            ("content/referenced.png", old_object),
+            ("content/recent.png", now.timestamp()),
+            ("content/reuploaded.png", old_object),
+            ("uploads/stale.png", old_object),
+            ("content/orphan.png", old_object),
+            ("variants/orphan/w640.webp", old_object),
+            ("content/orphan-recent.png", now.timestamp()),
+        ]
+        .into_iter()
+        .map(|(key, modified)| (key.to_string(), modified))
+        .collect();
+        let referenced: HashSet<String> = ["referenced", "broken"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+
+        let plan = plan(&contents, &objects, &referenced, BUCKET, cutoff);
+
+        assert_eq!(plan.report.unreferenced, vec!["unreferenced"]);
+        assert_eq!(plan.report.missing_objects, vec!["missing"]);
+        assert_eq!(plan.report.broken_references, vec!["broken"]);
+        assert_eq!(plan.report.stale_uploads, vec!["stale"]);
+        assert_eq!(
+            plan.report.orphaned_objects,
+            vec![
+                "content/orphan.png",
+                "uploads/unreferenced.png",
+                "variants/orphan/w640.webp"
+            ]
+        );
+        let collected: Vec<&str> = plan.contents.iter().map(|c| c.pk.as_str()).collect();
+        assert_eq!(
+            collected,
+            vec!["CONTENT#unreferenced", "CONTENT#missing", "CONTENT#stale"]
+        );
+    }
+
+    #[test]
+    fn plan_ignores_content_in_other_buckets() {
+        let old = Utc::now() - Duration::days(30);
+        let mut elsewhere = content("elsewhere", ContentStatus::Ready, old);
+        elsewhere.location = "s3://other/content/elsewhere.png".to_string();
+
+        let contents = [elsewhere];
+        let objects = HashMap::new();
+
+        let plan = plan(&contents, &objects, &HashSet::new(), BUCKET, Utc::now());
+        assert!(plan.contents.is_empty());
+        assert!(plan.report.missing_objects.is_empty());
+    }
+}
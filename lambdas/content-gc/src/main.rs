@@ -0,0 +1,20 @@
+use lambda_runtime::{run, service_fn, tracing, Error};
+mod event_handler;
+
+use event_handler::function_handler;
+use shared::config::Setting;
+use shared::context::AppContext;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let ctx = AppContext::load(&[
+        Setting::PostsTable,
+        Setting::ContentTable,
+        Setting::ContentBucket,
+    ])
+    .await?;
+
+    run(service_fn(|event| function_handler(&ctx, event))).await
+}
@@ -1,89 +1,274 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
-use aws_sdk_dynamodb::Client as DdbClient;
-use aws_sdk_s3::Client as S3Client;
 use base64::engine::general_purpose::STANDARD as base64_engine;
 use base64::Engine as _;
 use chrono::Utc;
 use lambda_runtime::LambdaEvent;
-use shared::api::get_author_id_from_request_context;
-use shared::db::create_content;
-use shared::models::{Content, CreateContentRequest, CreateContentResponse};
-use std::env;
-use tracing::info;
+use ring::digest::{digest, SHA256};
+use shared::api::{require_any_scope, AuthenticatedUser};
+use shared::context::AppContext;
+use shared::db::{
+    create_content, get_content, get_content_hash, get_post, put_content_hash, put_post,
+    touch_content,
+};
+use shared::errors::error_response;
+use shared::front_matter::{split_front_matter, FrontMatter};
+use shared::http::ApiRequest;
+use shared::markdown::post_metadata;
+use shared::media::MediaType;
+use shared::models::{
+    BlogPost, Content, ContentHash, ContentStatus, CreateContentRequest, CreateContentResponse,
+};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
     let request = event.payload;
+    let user = match require_any_scope(&request, &["author.write", "admin.write"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
     let body = request.body.ok_or("Missing body")?;
 
-    let request_context = request.request_context;
-    let author_id = get_author_id_from_request_context(request_context)?;
-
     let parsed: CreateContentRequest = serde_json::from_str(&body)?;
 
     info!("Create content request: {:#?}", parsed);
 
-    let content_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
-
-    // Upload content to S3
-    let bucket = env::var("BLOG_CONTENT_BUCKET")?;
-    let key = format!("content/{}.{}", content_id, parsed.file_extension);
-
-    let s3 = S3Client::new(&aws_config::load_from_env().await);
-
     let data = if parsed.is_base64_encoded {
         base64_engine.decode(parsed.body.trim())?
     } else {
         parsed.body.into_bytes()
     };
 
-    s3.put_object()
-        .bucket(&bucket)
-        .key(&key)
-        .body(aws_sdk_s3::primitives::ByteStream::from(data))
-        .content_type(parsed.content_type.clone())
-        .send()
-        .await?;
-
-    // Write metadata to DynamoDB
-    let content_pk = format!("CONTENT#{}", content_id);
-    let location = format!("s3://{}/{}", bucket, key);
-
-    let ddb = DdbClient::new(&aws_config::load_from_env().await);
-    let table_name = env::var("BLOG_CONTENT_TABLE").expect("BLOG_CONTENT_TABLE not set");
-
-    let content = Content {
-        pk: content_pk.clone(),
-        sk: "META".to_string(),
-        content_type: parsed.content_type.clone(),
-        location: location.to_string(),
-        created_at,
-        author_id,
+    let media_type = match MediaType::for_upload(
+        &user,
+        &parsed.content_type,
+        &parsed.file_extension,
+        data.len(),
+        ctx.config.limits.max_upload_bytes,
+    )
+    .and_then(|media_type| media_type.check_signature(&data).map(|_| media_type))
+    {
+        Ok(media_type) => media_type,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    // Markdown with front matter creates or updates its post in the same request, and is
+    // stored without the header.
+    let mut data = data;
+    let mut front_matter = None;
+    if media_type.content_type == "text/markdown" {
+        let Ok(markdown) = std::str::from_utf8(&data) else {
+            return Ok(error_response(415, "Markdown must be UTF-8"));
+        };
+        let (header, body) = match split_front_matter(markdown) {
+            Ok(split) => split,
+            Err(err) => return Ok(error_response(400, &err)),
+        };
+        if header.is_some() {
+            front_matter = header;
+            data = body.as_bytes().to_vec();
+        }
+    }
+
+    let table_name = ctx.config.content_table()?;
+    let sha256 = hex_sha256(&data);
+
+    // Uploading the same bytes again returns the author's existing content.
+    let existing = find_duplicate(ctx, table_name, &sha256, &user.sub, media_type).await?;
+    let content_id = match &existing {
+        Some((content_id, _)) => content_id.clone(),
+        None => Uuid::new_v4().to_string(),
     };
 
-    match create_content(&ddb, &table_name, &content).await {
-        Ok(_) => {
-            let response = CreateContentResponse {
-                content_id,
-                content_type: content.content_type,
-                location: content.location,
-                created_at: content.created_at,
-                author_id: content.author_id,
+    let post = match front_matter {
+        Some(front_matter) => {
+            match post_from_front_matter(ctx, &user, front_matter, &content_id).await {
+                Ok(mut linked) => {
+                    linked.metadata = Some(post_metadata(&String::from_utf8_lossy(&data)));
+                    Some(linked)
+                }
+                Err(response) => return Ok(response),
+            }
+        }
+        None => None,
+    };
+
+    let (content, status_code) = match existing {
+        Some((_, content)) => {
+            info!("Content {} already holds these bytes", content_id);
+            (content, 200)
+        }
+        None => {
+            let size = data.len() as i64;
+
+            // Upload content to S3
+            let bucket = ctx.config.content_bucket()?;
+            let key = format!("content/{}.{}", content_id, media_type.extension());
+
+            ctx.s3
+                .put_object()
+                .bucket(bucket)
+                .key(&key)
+                .body(aws_sdk_s3::primitives::ByteStream::from(data))
+                .content_type(media_type.content_type)
+                .send()
+                .await?;
+
+            // Write metadata to DynamoDB
+            let created_at = Utc::now().to_rfc3339();
+            let content = Content {
+                pk: format!("CONTENT#{}", content_id),
+                sk: "META".to_string(),
+                content_type: media_type.content_type.to_string(),
+                location: format!("s3://{}/{}", bucket, key),
+                created_at: created_at.clone(),
+                last_uploaded_at: None,
+                author_id: user.sub.clone(),
+                status: ContentStatus::Ready,
+                size: Some(size),
+                variants: Vec::new(),
+                sha256: Some(sha256.clone()),
+            };
+
+            if let Err(err) = create_content(&ctx.dynamodb, table_name, &content).await {
+                return Ok(error_response(409, &err.to_string()));
+            }
+
+            let hash = ContentHash {
+                pk: format!("HASH#{}", sha256),
+                sk: format!("AUTHOR#{}", user.sub),
+                content_id: content_id.clone(),
+                created_at,
             };
-            Ok(ApiGatewayProxyResponse {
-                status_code: 201,
-                body: Some(Body::Text(serde_json::to_string(&response)?)),
-                ..Default::default()
-            })
+            // Without the index entry the next identical upload is just stored again.
+            if let Err(err) = put_content_hash(&ctx.dynamodb, table_name, &hash).await {
+                warn!("Could not index content {} by digest: {}", content_id, err);
+            }
+
+            (content, 201)
         }
-        Err(err) => Ok(ApiGatewayProxyResponse {
-            status_code: 409,
-            body: Some(Body::Text(format!("{{\"error\": \"{}\"}}", err))),
-            ..Default::default()
-        }),
+    };
+
+    if let Some(post) = &post {
+        info!("Blog post from front matter: {:?}", post);
+        put_post(&ctx.dynamodb, ctx.config.posts_table()?, post).await?;
     }
+
+    let response = CreateContentResponse {
+        content_id,
+        content_type: content.content_type,
+        location: content.location,
+        created_at: content.created_at,
+        author_id: content.author_id,
+        status: content.status,
+        post_id: post.map(|post| post.pk),
+    };
+    Ok(ApiGatewayProxyResponse {
+        status_code,
+        body: Some(Body::Text(serde_json::to_string(&response)?)),
+        ..Default::default()
+    })
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    digest(&SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The author's ready content with the same digest and type, with its id. The index
+/// can outlive the content it points to, so the content item is checked too.
+///
+/// A match is stamped with `lastUploadedAt`, so garbage collection does not delete
+/// content that was just handed out again because it was first stored long ago.
+async fn find_duplicate(
+    ctx: &AppContext,
+    table_name: &str,
+    sha256: &str,
+    author_id: &str,
+    media_type: &MediaType,
+) -> Result<Option<(String, Content)>, String> {
+    let Some(hash) = get_content_hash(&ctx.dynamodb, table_name, sha256, author_id).await? else {
+        return Ok(None);
+    };
+    let pk = format!("CONTENT#{}", hash.content_id);
+    let content = get_content(&ctx.dynamodb, table_name, &pk)
+        .await?
+        .filter(|content| {
+            content.status == ContentStatus::Ready
+                && content.author_id == author_id
+                && content.content_type == media_type.content_type
+                && content.sha256.as_deref() == Some(sha256)
+        });
+    let Some(mut content) = content else {
+        return Ok(None);
+    };
+
+    let uploaded_at = Utc::now().to_rfc3339();
+    if !touch_content(&ctx.dynamodb, table_name, &pk, &uploaded_at).await? {
+        return Ok(None);
+    }
+    content.last_uploaded_at = Some(uploaded_at);
+    Ok(Some((hash.content_id, content)))
+}
+
+/// Builds the post described by a markdown upload's front matter, linked to the new
+/// content. `post` in the front matter names an existing post to update, which must
+/// belong to the caller unless they have `admin.write`.
+async fn post_from_front_matter(
+    ctx: &AppContext,
+    user: &AuthenticatedUser,
+    front_matter: FrontMatter,
+    content_id: &str,
+) -> Result<BlogPost, ApiGatewayProxyResponse> {
+    let title = front_matter.title.trim().to_string();
+    if title.is_empty() {
+        return Err(error_response(400, "Front matter title must not be empty"));
+    }
+    let created_at = front_matter
+        .created_at()
+        .map_err(|err| error_response(400, &err))?;
+    let published = front_matter.published();
+
+    let Some(post_id) = &front_matter.post else {
+        return Ok(BlogPost {
+            pk: format!("POST#post-{}", Uuid::new_v4()),
+            sk: "META".to_string(),
+            title,
+            author_id: user.sub.clone(),
+            tags: front_matter.tags,
+            published,
+            created_at: created_at.unwrap_or_else(|| Utc::now().to_rfc3339()),
+            content_key: content_id.to_string(),
+            metadata: None,
+        });
+    };
+
+    let table_name = ctx
+        .config
+        .posts_table()
+        .map_err(|err| error_response(500, &err))?;
+    let pk = format!("POST#{}", post_id.strip_prefix("POST#").unwrap_or(post_id));
+    let existing = get_post(&ctx.dynamodb, table_name, &pk)
+        .await
+        .map_err(|err| error_response(500, &err))?
+        .ok_or_else(|| error_response(404, "Post not found"))?;
+    if existing.author_id != user.sub && !user.has_scope("admin.write") {
+        return Err(error_response(403, "Forbidden"));
+    }
+
+    Ok(BlogPost {
+        title,
+        tags: front_matter.tags,
+        published,
+        created_at: created_at.unwrap_or(existing.created_at),
+        content_key: content_id.to_string(),
+        ..existing
+    })
 }
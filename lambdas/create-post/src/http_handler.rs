@@ -1,60 +1,102 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
-use aws_sdk_dynamodb::Client;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::HeaderMap;
 use chrono::Utc;
 use lambda_runtime::LambdaEvent;
-use shared::api::get_author_id_from_request_context;
-use shared::db::create_post;
-use shared::models::{BlogPost, CreatePostRequest};
-use std::env;
+use serde_json::json;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::db::{create_post, get_content};
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use shared::markdown::post_metadata;
+use shared::models::{BlogPost, ContentStatus, CreatePostRequest};
 use tracing::info;
 use uuid::Uuid;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
     let request = event.payload;
 
-    let request_context = request.request_context;
+    let user = match require_any_scope(&request, &["author.write", "admin.write"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
     let body = request.body.ok_or("Missing body")?;
 
-    let author_id = get_author_id_from_request_context(request_context)?;
-
     let post_request: CreatePostRequest = serde_json::from_str(&body)?;
 
     info!("Post request: {:#?}", post_request);
 
     let post_id = format!("post-{}", Uuid::new_v4());
     let created_at = Utc::now().to_rfc3339();
-    let content_key = Uuid::new_v4().to_string(); // ✅ Store content separately in S3
     let post_pk = format!("POST#{}", post_id);
 
-    let client = Client::new(&aws_config::load_from_env().await);
-    let table_name = env::var("BLOG_POSTS_TABLE").expect("BLOG_POSTS_TABLE not set");
+    // The post's body is a content item the caller uploaded beforehand.
+    let content_key = post_request.content_key.trim();
+    let content_key = content_key.strip_prefix("CONTENT#").unwrap_or(content_key);
+    if content_key.is_empty() {
+        return Ok(error_response(400, "contentKey is required"));
+    }
+    let content_table = ctx.config.content_table()?;
+    let Some(content) = get_content(
+        &ctx.dynamodb,
+        content_table,
+        &format!("CONTENT#{}", content_key),
+    )
+    .await?
+    else {
+        return Ok(error_response(422, "contentKey does not match any content"));
+    };
+    if content.author_id != user.sub && !user.has_scope("admin.write") {
+        return Ok(error_response(403, "Forbidden"));
+    }
+    if content.status == ContentStatus::Pending {
+        return Ok(error_response(409, "Content upload has not been finalized"));
+    }
+
+    let metadata = if content.is_markdown() {
+        let bucket = ctx.config.content_bucket()?;
+        let key = content
+            .s3_key(bucket)
+            .ok_or("Invalid content location path")?;
+        let object = ctx.s3.get_object().bucket(bucket).key(key).send().await?;
+        let bytes = object.body.collect().await?.into_bytes();
+        Some(post_metadata(&String::from_utf8_lossy(&bytes)))
+    } else {
+        None
+    };
+
+    let table_name = ctx.config.posts_table()?;
 
     let blog_post = BlogPost {
         pk: post_pk.clone(),
         sk: "META".to_string(),
         title: post_request.title,
-        author_id: author_id.to_string(),
+        author_id: user.sub,
         tags: post_request.tags,
         published: post_request.published,
         created_at,
-        content_key,
+        content_key: content_key.to_string(),
+        metadata,
     };
 
     info!("Blog post: {:?}", blog_post);
 
-    match create_post(&client, &table_name, &blog_post).await {
-        Ok(_) => Ok(ApiGatewayProxyResponse {
-            status_code: 201,
-            body: Some(Body::Text(format!("{{\"post_id\": \"{}\"}}", post_pk))),
-            ..Default::default()
-        }),
-        Err(err) => Ok(ApiGatewayProxyResponse {
-            status_code: 409,
-            body: Some(Body::Text(format!("{{\"error\": \"{}\"}}", err))),
-            ..Default::default()
-        }),
+    if let Err(err) = create_post(&ctx.dynamodb, table_name, &blog_post).await {
+        return Ok(error_response(409, &err.to_string()));
     }
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 201,
+        headers: header_map,
+        body: Some(Body::Text(json!({ "post_id": post_pk }).to_string())),
+        ..Default::default()
+    })
 }
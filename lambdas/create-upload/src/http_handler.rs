@@ -0,0 +1,120 @@
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::HeaderMap;
+use aws_sdk_s3::presigning::PresigningConfig;
+use chrono::{Duration as ChronoDuration, Utc};
+use lambda_runtime::LambdaEvent;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::db::create_content;
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use shared::media::{MediaType, UPLOAD_PREFIX};
+use shared::models::{Content, ContentStatus, CreateUploadRequest, CreateUploadResponse};
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// Reserves a pending content item and returns a presigned PUT URL for the file.
+///
+/// The declared type must be on the media allowlist for the caller's scopes. The URL
+/// is signed for that type and the exact size, so S3 rejects uploads that differ. It
+/// writes to a staging key under `uploads/`; the item stays `pending` until
+/// finalize-upload has checked the object and copied it to `content/`, out of the URL's
+/// reach.
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let request = event.payload;
+    let user = match require_any_scope(&request, &["author.write", "admin.write"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    let Some(body) = request.body else {
+        return Ok(error_response(400, "Missing body"));
+    };
+    let parsed: CreateUploadRequest = match serde_json::from_str(&body) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(error_response(
+                400,
+                &format!("Invalid upload request: {}", e),
+            ))
+        }
+    };
+
+    info!("Create upload request: {:#?}", parsed);
+
+    if parsed.size <= 0 {
+        return Ok(error_response(400, "size must be positive"));
+    }
+    let media_type = match MediaType::for_upload(
+        &user,
+        &parsed.content_type,
+        &parsed.file_extension,
+        parsed.size as usize,
+        usize::try_from(ctx.config.limits.max_presigned_upload_bytes).unwrap_or(0),
+    ) {
+        Ok(media_type) => media_type,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    let bucket = ctx.config.content_bucket()?;
+    let table_name = ctx.config.content_table()?;
+
+    let content_id = Uuid::new_v4().to_string();
+    let key = format!("{}{}.{}", UPLOAD_PREFIX, content_id, media_type.extension());
+
+    let ttl = ctx.config.limits.upload_url_ttl;
+    let presigned = ctx
+        .s3
+        .put_object()
+        .bucket(bucket)
+        .key(&key)
+        .content_type(media_type.content_type)
+        .content_length(parsed.size)
+        .presigned(PresigningConfig::expires_in(Duration::from_secs(ttl))?)
+        .await?;
+
+    let content = Content {
+        pk: format!("CONTENT#{}", content_id),
+        sk: "META".to_string(),
+        content_type: media_type.content_type.to_string(),
+        location: format!("s3://{}/{}", bucket, key),
+        created_at: Utc::now().to_rfc3339(),
+        last_uploaded_at: None,
+        author_id: user.sub,
+        status: ContentStatus::Pending,
+        size: Some(parsed.size),
+        variants: Vec::new(),
+        sha256: None,
+    };
+
+    if let Err(err) = create_content(&ctx.dynamodb, table_name, &content).await {
+        return Ok(error_response(409, &err));
+    }
+
+    let response = CreateUploadResponse {
+        content_id,
+        upload_url: presigned.uri().to_string(),
+        method: presigned.method().to_string(),
+        headers: presigned
+            .headers()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        expires_at: (Utc::now() + ChronoDuration::seconds(ttl as i64)).to_rfc3339(),
+    };
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 201,
+        headers: header_map,
+        body: Some(Body::Text(serde_json::to_string(&response)?)),
+        ..Default::default()
+    })
+}
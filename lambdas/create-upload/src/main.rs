@@ -0,0 +1,17 @@
+use create_upload::http_handler::function_handler;
+use lambda_runtime::{run, service_fn, tracing, Error};
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::http::handle_http_event;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let ctx = AppContext::load(&[Setting::ContentTable, Setting::ContentBucket]).await?;
+
+    run(service_fn(|event| {
+        handle_http_event(&ctx, event, |event| function_handler(&ctx, event))
+    }))
+    .await
+}
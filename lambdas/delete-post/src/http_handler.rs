@@ -1,30 +1,51 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
-use aws_lambda_events::encodings::Body;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use lambda_runtime::LambdaEvent;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::db::{delete_post, get_post};
+use shared::errors::error_response;
+use shared::http::ApiRequest;
 use tracing::info;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+/// Deletes the post named by `?id=` along with its comments. Its content is left for
+/// content-gc, which collects it once nothing else refers to it.
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let request_context = event.payload.request_context;
-    let authorizer = request_context.authorizer;
+    let request = event.payload;
+    let user = match require_any_scope(&request, &["author.delete", "admin.delete"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
 
-    info!("Authorizer: {:?}", authorizer);
+    let post_id = request
+        .query_string_parameters
+        .first("id")
+        .or_else(|| request.path_parameters.get("id").map(String::as_str))
+        .unwrap_or_default()
+        .trim();
+    let post_id = post_id.strip_prefix("POST#").unwrap_or(post_id);
+    if post_id.is_empty() {
+        return Ok(error_response(400, "id is required"));
+    }
 
-    let claims = authorizer
-        .fields
-        .get("claims")
-        .ok_or("Missing claims in fields")?
-        .as_object()
-        .ok_or("Claims is not object")?;
+    let table_name = ctx.config.posts_table()?;
+    let pk = format!("POST#{}", post_id);
+    let Some(post) = get_post(&ctx.dynamodb, table_name, &pk).await? else {
+        return Ok(error_response(404, "Post not found"));
+    };
 
-    info!("Claims: {:?}", claims);
+    let is_admin = user.has_scope("admin.delete") || user.claims.is_admin(ctx.config.stage()?);
+    if post.author_id != user.sub && !is_admin {
+        return Ok(error_response(403, "Forbidden"));
+    }
 
-    // TODO extract group id from claims
+    delete_post(&ctx.dynamodb, table_name, &pk).await?;
+    info!("Post {} deleted by {} (admin: {})", pk, user.sub, is_admin);
 
     Ok(ApiGatewayProxyResponse {
-        status_code: 200,
-        body: Some(Body::Text("Hello, world!".to_string())),
+        status_code: 204,
         ..Default::default()
     })
 }
@@ -1,11 +1,17 @@
+use delete_post::http_handler::function_handler;
 use lambda_runtime::{run, service_fn, tracing, Error};
-mod http_handler;
-
-use http_handler::function_handler;
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::http::handle_http_event;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
 
-    run(service_fn(function_handler)).await
+    let ctx = AppContext::load(&[Setting::PostsTable, Setting::Stage]).await?;
+
+    run(service_fn(|event| {
+        handle_http_event(&ctx, event, |event| function_handler(&ctx, event))
+    }))
+    .await
 }
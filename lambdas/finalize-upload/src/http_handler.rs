@@ -0,0 +1,187 @@
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::HeaderMap;
+use aws_sdk_s3::error::SdkError;
+use lambda_runtime::LambdaEvent;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::db::{get_content, mark_content_ready};
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use shared::media::{MediaType, SNIFF_LEN, UPLOAD_PREFIX};
+use shared::models::{ContentStatus, CreateContentResponse};
+use tracing::{info, warn};
+
+/// Checks that a presigned upload arrived as reserved, copies it from its staging key to
+/// `content/` and marks its content `ready`.
+///
+/// Objects that do not match the reserved type and size, or whose leading bytes are
+/// not of that type, are deleted, so a new upload can be reserved.
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let request = event.payload;
+    let user = match require_any_scope(&request, &["author.write", "admin.write"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    let table_name = ctx.config.content_table()?;
+    let bucket = ctx.config.content_bucket()?;
+
+    let content_id = request
+        .path_parameters
+        .get("id")
+        .cloned()
+        .unwrap_or_default();
+    if content_id.is_empty() {
+        return Ok(error_response(400, "Missing content id"));
+    }
+    let pk = format!("CONTENT#{}", content_id);
+
+    let Some(mut content) = get_content(&ctx.dynamodb, table_name, &pk).await? else {
+        return Ok(error_response(404, "Content not found"));
+    };
+    if content.author_id != user.sub {
+        return Ok(error_response(403, "Forbidden"));
+    }
+
+    if content.status == ContentStatus::Pending {
+        let key = content
+            .s3_key(bucket)
+            .ok_or("Invalid content location path")?
+            .to_string();
+
+        let head = match ctx.s3.head_object().bucket(bucket).key(&key).send().await {
+            Ok(head) => head,
+            Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                return Ok(error_response(409, "Upload has not completed"));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let size = head.content_length.unwrap_or_default();
+        let content_type = head.content_type.unwrap_or_default();
+        let etag = head.e_tag.ok_or("Uploaded object has no ETag")?;
+        info!(
+            "Uploaded object {}: {} bytes of {}",
+            key, size, content_type
+        );
+
+        if Some(size) != content.size || content_type != content.content_type {
+            warn!(
+                "Upload {} does not match its reservation ({:?} bytes of {})",
+                content_id, content.size, content.content_type
+            );
+            ctx.s3
+                .delete_object()
+                .bucket(bucket)
+                .key(&key)
+                .send()
+                .await?;
+            return Ok(error_response(
+                422,
+                "Uploaded file does not match the declared type and size",
+            ));
+        }
+
+        if let Some(media_type) = MediaType::find(&content.content_type) {
+            let object = match ctx
+                .s3
+                .get_object()
+                .bucket(bucket)
+                .key(&key)
+                .range(format!("bytes=0-{}", SNIFF_LEN - 1))
+                .if_match(&etag)
+                .send()
+                .await
+            {
+                Ok(object) => object,
+                Err(err) if is_precondition_failed(&err) => {
+                    return Ok(error_response(409, "Upload changed while it was checked"));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let head = object.body.collect().await?.into_bytes();
+
+            if let Err(err) = media_type.check_signature(&head) {
+                warn!("Upload {} failed content sniffing: {:?}", content_id, err);
+                ctx.s3
+                    .delete_object()
+                    .bucket(bucket)
+                    .key(&key)
+                    .send()
+                    .await?;
+                return Ok(err.into_response());
+            }
+        }
+
+        // The presigned URL stays valid after this check, so the content is served from
+        // a copy of exactly the checked object, under a key the URL cannot write to.
+        let final_key = match key.strip_prefix(UPLOAD_PREFIX) {
+            Some(name) => {
+                let final_key = format!("content/{}", name);
+                let copied = ctx
+                    .s3
+                    .copy_object()
+                    .bucket(bucket)
+                    .key(&final_key)
+                    .copy_source(format!("{}/{}", bucket, key))
+                    .copy_source_if_match(&etag)
+                    .send()
+                    .await;
+                match copied {
+                    Ok(_) => final_key,
+                    Err(err) if is_precondition_failed(&err) => {
+                        return Ok(error_response(409, "Upload changed while it was checked"));
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            // Reserved before uploads were staged.
+            None => key.clone(),
+        };
+        let location = format!("s3://{}/{}", bucket, final_key);
+
+        if !mark_content_ready(&ctx.dynamodb, table_name, &pk, &user.sub, size, &location).await? {
+            return Ok(error_response(409, "Upload was already finalized"));
+        }
+        content.status = ContentStatus::Ready;
+        content.location = location;
+
+        if final_key != key {
+            // content-gc collects the staging object if this fails.
+            if let Err(err) = ctx.s3.delete_object().bucket(bucket).key(&key).send().await {
+                warn!("Could not delete staged upload {}: {}", key, err);
+            }
+        }
+    }
+
+    let response = CreateContentResponse {
+        content_id,
+        content_type: content.content_type,
+        location: content.location,
+        created_at: content.created_at,
+        author_id: content.author_id,
+        status: content.status,
+        post_id: None,
+    };
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 200,
+        headers: header_map,
+        body: Some(Body::Text(serde_json::to_string(&response)?)),
+        ..Default::default()
+    })
+}
+
+/// Whether S3 rejected a conditional request because the object's ETag changed.
+fn is_precondition_failed<E>(err: &SdkError<E>) -> bool {
+    err.raw_response()
+        .is_some_and(|response| response.status().as_u16() == 412)
+}
@@ -0,0 +1,54 @@
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::HeaderMap;
+use lambda_runtime::LambdaEvent;
+use shared::api::AuthenticatedUser;
+use shared::context::AppContext;
+use shared::db::get_author_profile;
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+
+/// Path id that resolves to the caller's own profile.
+const OWN_PROFILE_ID: &str = "me";
+
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let table_name = ctx.config.posts_table()?;
+
+    let request = event.payload;
+
+    let author_id = request
+        .path_parameters
+        .get("id")
+        .cloned()
+        .unwrap_or_default();
+    if author_id.is_empty() {
+        return Ok(error_response(400, "Missing author id"));
+    }
+
+    let author_id = if author_id == OWN_PROFILE_ID {
+        match AuthenticatedUser::from_request(&request) {
+            Ok(user) => user.sub,
+            Err(err) => return Ok(err.into_response()),
+        }
+    } else {
+        author_id
+    };
+
+    let Some(profile) = get_author_profile(&ctx.dynamodb, table_name, &author_id).await? else {
+        return Ok(error_response(404, "Author not found"));
+    };
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 200,
+        headers: header_map,
+        body: Some(Body::Text(serde_json::to_string(&profile)?)),
+        ..Default::default()
+    })
+}
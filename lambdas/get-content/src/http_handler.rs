@@ -1,21 +1,30 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::{
+    ACCEPT_RANGES, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, RANGE, VARY,
+};
 use aws_lambda_events::http::HeaderMap;
-use aws_sdk_dynamodb::Client as DdbClient;
-use aws_sdk_s3::Client as S3Client;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
 use base64::engine::general_purpose::STANDARD as base64_engine;
 use base64::Engine as _;
 use lambda_runtime::LambdaEvent;
+use shared::context::AppContext;
 use shared::db::get_content;
-use shared::errors::ApiErrorResponse;
-use shared::models::Content;
-use std::env;
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use shared::images::select_variant;
+use shared::markdown::render_html;
+use shared::models::{Content, ContentStatus};
+use std::time::Duration;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let table_name = env::var("BLOG_CONTENT_TABLE").expect("BLOG_CONTENT_TABLE not set");
-    let bucket = env::var("BLOG_CONTENT_BUCKET").expect("BLOG_CONTENT_BUCKET not set");
+    let table_name = ctx.config.content_table()?;
+    let bucket = ctx.config.content_bucket()?;
 
     let request = event.payload;
     let content_id = request
@@ -25,49 +34,236 @@ pub(crate) async fn function_handler(
 
     let pk = format!("CONTENT#{}", content_id);
 
-    let ddb = DdbClient::new(&aws_config::load_from_env().await);
-    let maybe_content: Option<Content> = get_content(&ddb, &table_name, &pk).await?;
+    let maybe_content: Option<Content> = get_content(&ctx.dynamodb, table_name, &pk).await?;
 
-    if maybe_content.is_none() {
-        let error = serde_json::to_string(&ApiErrorResponse::new("Content not found"))?;
+    // Uploads that were never finalized are not content yet.
+    let maybe_content = maybe_content.filter(|content| content.status == ContentStatus::Ready);
+
+    let Some(content) = maybe_content else {
+        return Ok(error_response(404, "Content not found"));
+    };
+
+    let header = |name| request.headers.get(name).and_then(|v| v.to_str().ok());
+
+    let is_markdown = content.is_markdown();
+    let render = match request.query_string_parameters.first("format") {
+        Some("html") if is_markdown => true,
+        Some("html") => {
+            return Ok(error_response(
+                406,
+                "Only markdown content can be rendered as HTML",
+            ))
+        }
+        Some(_) => return Ok(error_response(400, "format must be html")),
+        None => is_markdown && request.accepts("text/html"),
+    };
+    if render {
+        let key = content
+            .s3_key(bucket)
+            .ok_or("Invalid content location path")?;
+        return html_response(ctx, bucket, key).await;
+    }
+
+    let width = match request.query_string_parameters.first("w") {
+        Some(w) => match w.parse::<u32>() {
+            Ok(w) if w > 0 => Some(w),
+            _ => return Ok(error_response(400, "w must be a positive integer")),
+        },
+        None => None,
+    };
+
+    // Processed images are served from a rendition, which also has no EXIF metadata.
+    let variant = select_variant(
+        &content.variants,
+        &content.content_type,
+        width,
+        request.accepts("image/webp"),
+    );
+    let (key, content_type, known_size) = match variant {
+        Some(variant) => (
+            variant.key.clone(),
+            variant.content_type.clone(),
+            Some(variant.size),
+        ),
+        None => (
+            content
+                .s3_key(bucket)
+                .ok_or("Invalid content location path")?
+                .to_string(),
+            content.content_type.clone(),
+            content.size,
+        ),
+    };
+    // The representation depends on Accept for markdown, and for images whenever there
+    // is a WebP rendition to pick.
+    let vary_accept = is_markdown || !content.variants.is_empty();
+
+    let delivery = &ctx.config.delivery;
+    let size = match known_size {
+        Some(size) => Some(size),
+        // Content stored before sizes were recorded.
+        None if delivery.redirect => {
+            let head = ctx.s3.head_object().bucket(bucket).key(&key).send().await?;
+            head.content_length
+        }
+        None => None,
+    };
+
+    if !delivery.is_inline(&content_type, size) {
+        let presigned = ctx
+            .s3
+            .get_object()
+            .bucket(bucket)
+            .key(&key)
+            .presigned(PresigningConfig::expires_in(Duration::from_secs(
+                delivery.url_ttl,
+            ))?)
+            .await?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, presigned.uri().parse()?);
+        // Clients must not reuse the redirect after the URL it points to expires.
+        headers.insert(
+            CACHE_CONTROL,
+            format!("private, max-age={}", delivery.url_ttl / 2).parse()?,
+        );
+        if vary_accept {
+            headers.insert(VARY, "Accept".parse()?);
+        }
         return Ok(ApiGatewayProxyResponse {
-            status_code: 404,
-            body: Some(Body::Text(error)),
+            status_code: 302,
+            headers,
             ..Default::default()
         });
     }
 
-    let content = maybe_content.unwrap();
+    // S3 evaluates the validators and range itself; If-None-Match takes precedence
+    // over If-Modified-Since as in RFC 9110.
+    let if_none_match = header(IF_NONE_MATCH);
+    let if_modified_since = if_none_match
+        .is_none()
+        .then(|| header(IF_MODIFIED_SINCE))
+        .flatten()
+        .and_then(|date| DateTime::from_str(date, DateTimeFormat::HttpDate).ok());
 
-    let s3 = S3Client::new(&aws_config::load_from_env().await);
-    let key = content
-        .location
-        .strip_prefix(&format!("s3://{}/", bucket))
-        .ok_or("Invalid content location path")?
-        .to_string();
+    let result = match ctx
+        .s3
+        .get_object()
+        .bucket(bucket)
+        .key(&key)
+        .set_range(header(RANGE).map(str::to_string))
+        .set_if_none_match(if_none_match.map(str::to_string))
+        .set_if_modified_since(if_modified_since)
+        .send()
+        .await
+    {
+        Ok(result) => result,
+        Err(err) => {
+            let Some(raw) = err.raw_response() else {
+                return Err(err.into());
+            };
+            match raw.status().as_u16() {
+                304 => {
+                    let mut headers = HeaderMap::new();
+                    headers.insert(CACHE_CONTROL, "public, max-age=60".parse()?);
+                    if let Some(etag) = raw.headers().get("etag") {
+                        headers.insert(ETAG, etag.parse()?);
+                    }
+                    if vary_accept {
+                        headers.insert(VARY, "Accept".parse()?);
+                    }
+                    return Ok(ApiGatewayProxyResponse {
+                        status_code: 304,
+                        headers,
+                        ..Default::default()
+                    });
+                }
+                416 => {
+                    let mut response = error_response(416, "Range not satisfiable");
+                    if let Some(size) = size {
+                        response
+                            .headers
+                            .insert(CONTENT_RANGE, format!("bytes */{}", size).parse()?);
+                    }
+                    return Ok(response);
+                }
+                _ => return Err(err.into()),
+            }
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, content_type.parse()?);
+    headers.insert(CACHE_CONTROL, "public, max-age=60".parse()?);
+    headers.insert(ACCEPT_RANGES, "bytes".parse()?);
+    if vary_accept {
+        headers.insert(VARY, "Accept".parse()?);
+    }
+    if let Some(etag) = &result.e_tag {
+        headers.insert(ETAG, etag.parse()?);
+    }
+    if let Some(last_modified) = result
+        .last_modified
+        .and_then(|date| date.fmt(DateTimeFormat::HttpDate).ok())
+    {
+        headers.insert(LAST_MODIFIED, last_modified.parse()?);
+    }
+    if let Some(content_length) = result.content_length {
+        headers.insert(CONTENT_LENGTH, content_length.into());
+    }
+    let status_code = match &result.content_range {
+        Some(content_range) => {
+            headers.insert(CONTENT_RANGE, content_range.parse()?);
+            206
+        }
+        None => 200,
+    };
 
-    let result = s3.get_object().bucket(&bucket).key(&key).send().await?;
     let bytes = result.body.collect().await?.into_bytes();
-    let content_type = content.content_type;
 
-    let (body, is_base64_encoded) =
-        if content_type.starts_with("text/") || content_type == "application/json" {
-            (Body::Text(String::from_utf8(bytes.to_vec())?), false)
-        } else {
-            let base64 = base64_engine.encode(&bytes);
-            (Body::Text(base64), true)
-        };
+    // A range can split a multi-byte character, so partial text falls back to base64.
+    let text = if content_type.starts_with("text/") || content_type == "application/json" {
+        String::from_utf8(bytes.to_vec()).ok()
+    } else {
+        None
+    };
+    let (body, is_base64_encoded) = match text {
+        Some(text) => (Body::Text(text), false),
+        None => (Body::Text(base64_engine.encode(&bytes)), true),
+    };
 
     Ok(ApiGatewayProxyResponse {
-        status_code: 200,
-        headers: {
-            let mut headers = HeaderMap::new();
-            headers.insert("Content-Type", content_type.parse()?);
-            headers.insert("Cache-Control", "public, max-age=60".parse()?);
-            headers
-        },
+        status_code,
+        headers,
         body: Some(body),
         is_base64_encoded,
         ..Default::default()
     })
 }
+
+/// Renders a markdown object to sanitized HTML. Range and conditional headers apply to
+/// the stored markdown, so they are ignored here.
+async fn html_response(
+    ctx: &AppContext,
+    bucket: &str,
+    key: &str,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let object = ctx.s3.get_object().bucket(bucket).key(key).send().await?;
+    let bytes = object.body.collect().await?.into_bytes();
+    let markdown = String::from_utf8_lossy(&bytes);
+
+    let delivery = &ctx.config.delivery;
+    let html = render_html(&markdown, |content_id| delivery.content_url(content_id));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "text/html; charset=utf-8".parse()?);
+    headers.insert(CACHE_CONTROL, "public, max-age=60".parse()?);
+    headers.insert(VARY, "Accept".parse()?);
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 200,
+        headers,
+        body: Some(Body::Text(html)),
+        ..Default::default()
+    })
+}
@@ -1,21 +1,31 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
 use aws_lambda_events::http::header::CONTENT_TYPE;
 use aws_lambda_events::http::HeaderMap;
-use aws_sdk_dynamodb::Client;
 use lambda_runtime::LambdaEvent;
 use serde_json::json;
-use shared::db::fetch_post_and_comments;
-use shared::errors::ApiErrorResponse;
-use std::env;
+use shared::context::AppContext;
+use shared::db::{fetch_author_summaries, fetch_post_and_comments, get_content};
+use shared::errors::{error_response, ApiErrorResponse};
+use shared::http::ApiRequest;
+use shared::markdown::render_html;
+use shared::models::{BlogPost, ContentStatus, PostContent, PostWithAuthor};
+use tracing::warn;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let client = Client::new(&aws_config::load_from_env().await);
-    let table_name = env::var("BLOG_POSTS_TABLE").expect("BLOG_POSTS_TABLE not set");
+    let table_name = ctx.config.posts_table()?;
 
-    let path_parameters = event.payload.path_parameters;
+    let request = event.payload;
+    let path_parameters = request.path_parameters;
+
+    // `?content=url|inline|html` embeds the post's content in the response.
+    let include = request.query_string_parameters.first("content");
+    if include.is_some_and(|include| !matches!(include, "url" | "inline" | "html")) {
+        return Ok(error_response(400, "content must be url, inline or html"));
+    }
 
     let mut header_map = HeaderMap::new();
     header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
@@ -31,9 +41,9 @@ pub(crate) async fn function_handler(
         });
     }
 
-    let (meta, comments) = fetch_post_and_comments(&client, &table_name, &post_id).await;
+    let (meta, comments) = fetch_post_and_comments(&ctx.dynamodb, table_name, &post_id).await;
 
-    if meta.is_none() {
+    let Some(post) = meta else {
         let error = serde_json::to_string(&ApiErrorResponse::new("Post not found"))?;
         return Ok(ApiGatewayProxyResponse {
             status_code: 404,
@@ -41,12 +51,34 @@ pub(crate) async fn function_handler(
             body: Some(Body::Text(error)),
             ..Default::default()
         });
-    }
+    };
 
-    let response_body = json!({
-        "meta": meta,
+    let author = match fetch_author_summaries(
+        &ctx.dynamodb,
+        table_name,
+        std::slice::from_ref(&post.author_id),
+    )
+    .await
+    {
+        Ok(mut summaries) => summaries.remove(&post.author_id),
+        Err(err) => {
+            warn!("Could not load author for post {}: {}", post_id, err);
+            None
+        }
+    };
+
+    let content = match include {
+        Some(include) => post_content(ctx, &post, include).await?,
+        None => None,
+    };
+
+    let mut response_body = json!({
+        "meta": PostWithAuthor { post, author },
         "comments": comments
     });
+    if let Some(content) = content {
+        response_body["content"] = serde_json::to_value(content)?;
+    }
 
     Ok(ApiGatewayProxyResponse {
         status_code: 200,
@@ -55,3 +87,56 @@ pub(crate) async fn function_handler(
         ..Default::default()
     })
 }
+
+/// Looks up the content linked to `post`. With `inline`, text small enough to be
+/// returned inline by get-content is included; with `html`, markdown is rendered.
+/// Posts whose `contentKey` does not match a ready content item have no content.
+async fn post_content(
+    ctx: &AppContext,
+    post: &BlogPost,
+    include: &str,
+) -> Result<Option<PostContent>, Box<dyn std::error::Error + Send + Sync>> {
+    let table_name = ctx.config.content_table()?;
+    let pk = format!("CONTENT#{}", post.content_key);
+    let Some(content) = get_content(&ctx.dynamodb, table_name, &pk)
+        .await?
+        .filter(|content| content.status == ContentStatus::Ready)
+    else {
+        return Ok(None);
+    };
+
+    let delivery = &ctx.config.delivery;
+    let embed = match include {
+        "html" => content.is_markdown(),
+        "inline" => {
+            content.content_type.starts_with("text/")
+                && content
+                    .size
+                    .is_some_and(|size| size <= delivery.inline_max_bytes)
+        }
+        _ => false,
+    };
+
+    let body = if embed {
+        let bucket = ctx.config.content_bucket()?;
+        let key = content
+            .s3_key(bucket)
+            .ok_or("Invalid content location path")?;
+        let object = ctx.s3.get_object().bucket(bucket).key(key).send().await?;
+        let bytes = object.body.collect().await?.into_bytes();
+        let text = String::from_utf8_lossy(&bytes);
+        Some(match include {
+            "html" => render_html(&text, |content_id| delivery.content_url(content_id)),
+            _ => text.into_owned(),
+        })
+    } else {
+        None
+    };
+
+    Ok(Some(PostContent {
+        url: delivery.content_url(&post.content_key),
+        content_id: post.content_key.clone(),
+        content_type: content.content_type,
+        body,
+    }))
+}
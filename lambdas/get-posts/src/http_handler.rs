@@ -1,33 +1,39 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
 use aws_lambda_events::http::header::CONTENT_TYPE;
 use aws_lambda_events::http::HeaderMap;
-use aws_sdk_dynamodb::Client;
 use lambda_runtime::LambdaEvent;
 use serde_json::json;
-use shared::db::fetch_published_posts;
+use shared::context::AppContext;
+use shared::db::{fetch_author_summaries, fetch_published_posts};
 use shared::errors::ApiErrorResponse;
-use std::env;
+use shared::http::ApiRequest;
+use shared::models::PostWithAuthor;
+use tracing::warn;
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
-    let client = Client::new(&aws_config::load_from_env().await);
-    let table_name = env::var("BLOG_POSTS_TABLE").expect("BLOG_POSTS_TABLE not set");
+    let table_name = ctx.config.posts_table()?;
 
     let query_string_parameters = event.payload.query_string_parameters;
 
     let mut header_map = HeaderMap::new();
     header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
 
+    let max_page_size = ctx.config.limits.max_page_size;
     let limit = query_string_parameters
         .first("limit")
-        .and_then(|l| l.parse().ok());
+        .and_then(|l| l.parse::<i32>().ok())
+        .map(|l| l.clamp(1, max_page_size))
+        .or(Some(max_page_size));
     let next_token = query_string_parameters
         .first("nextToken")
         .map(|s| s.to_string());
 
-    let (posts, next_token) = fetch_published_posts(&client, &table_name, limit, next_token).await;
+    let (posts, next_token) =
+        fetch_published_posts(&ctx.dynamodb, table_name, limit, next_token).await;
 
     if posts.is_empty() {
         let error = serde_json::to_string(&ApiErrorResponse::new("No posts found"))?;
@@ -39,6 +45,21 @@ pub(crate) async fn function_handler(
         });
     }
 
+    let author_ids: Vec<String> = posts.iter().map(|post| post.author_id.clone()).collect();
+    let authors = fetch_author_summaries(&ctx.dynamodb, table_name, &author_ids)
+        .await
+        .unwrap_or_else(|err| {
+            warn!("Could not load post authors: {}", err);
+            Default::default()
+        });
+    let posts: Vec<PostWithAuthor> = posts
+        .into_iter()
+        .map(|post| PostWithAuthor {
+            author: authors.get(&post.author_id).cloned(),
+            post,
+        })
+        .collect();
+
     let response_body = json!({
         "posts": posts,
         "nextToken": next_token
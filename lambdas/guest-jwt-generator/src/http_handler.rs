@@ -1,18 +1,23 @@
-use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use crate::self_signed::SelfSignedIssuer;
+use crate::token_cache::TokenCache;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 use aws_lambda_events::encodings::Body;
-use aws_lambda_events::http::header::CONTENT_TYPE;
-use aws_lambda_events::http::HeaderMap;
-use aws_sdk_cognitoidentityprovider::types::AuthFlowType;
+use aws_lambda_events::http::header::{CACHE_CONTROL, CONTENT_TYPE, RETRY_AFTER};
+use aws_lambda_events::http::{HeaderMap, HeaderValue, Method};
+use aws_sdk_cognitoidentityprovider::operation::initiate_auth::InitiateAuthOutput;
+use aws_sdk_cognitoidentityprovider::types::{AuthFlowType, AuthenticationResultType};
 use chrono::Utc;
 use lambda_runtime::LambdaEvent;
 use serde::{Deserialize, Serialize};
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::errors::error_response;
+use shared::http::ApiRequest;
 use std::collections::HashMap;
-use std::env;
-use tokio::sync::OnceCell;
-use tracing::info;
+use tracing::{info, warn};
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Tokens {
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Tokens {
     #[serde(rename = "accessToken")]
     access_token: String,
     #[serde(rename = "idToken")]
@@ -25,78 +30,109 @@ struct Tokens {
     token_type: String,
 }
 
-static CACHED_TOKEN: OnceCell<(String, i64)> = OnceCell::const_new();
-const CACHE_TTL: i64 = 900; // 15 minutes
-
-async fn get_cached_token() -> Option<Tokens> {
-    if let Some((cached_tokens, exp)) = CACHED_TOKEN.get() {
-        if *exp > Utc::now().timestamp() {
-            let tokens = serde_json::from_str(&cached_tokens.clone()).unwrap();
-            return Some(tokens);
-        }
-    }
-    None
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
 }
 
-async fn store_tokens(tokens_json: String) {
-    let exp = Utc::now().timestamp() + CACHE_TTL;
-    let _ = CACHED_TOKEN.set((tokens_json, exp));
+/// Refresh the cached guest session this long before Cognito says it expires.
+pub(crate) const TOKEN_SAFETY_MARGIN: i64 = 120;
+
+pub(crate) type GuestTokenCache = TokenCache<Tokens>;
+
+/// Served by the self-signed mode so authorizers can verify its tokens.
+const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// How guest tokens are issued, chosen on cold start.
+pub(crate) enum GuestIssuer {
+    /// Signs in to Cognito as the shared guest user.
+    Cognito(GuestTokenCache),
+    SelfSigned(SelfSignedIssuer),
 }
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<ApiGatewayProxyRequest>,
+fn json_response<T: Serialize>(
+    body: &T,
 ) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error>> {
-    info!("event: {:?}", event);
-
     let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
-
-    if let Some(cached_token) = get_cached_token().await {
-        info!("Using cached token");
-        return Ok(ApiGatewayProxyResponse {
-            status_code: 200,
-            headers,
-            body: Some(Body::Text(serde_json::to_string(&cached_token).unwrap())),
-            ..Default::default()
-        });
-    }
-    info!("No cached token found");
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 200,
+        headers,
+        body: Some(Body::Text(serde_json::to_string(body)?)),
+        ..Default::default()
+    })
+}
 
-    let config = aws_config::load_from_env().await;
+/// Cognito does not issue a new refresh token on `REFRESH_TOKEN_AUTH`, so the one used
+/// for the refresh is carried over.
+fn tokens_from_result(
+    authentication_result: AuthenticationResultType,
+    refresh_token: Option<&str>,
+) -> Result<Tokens, String> {
+    let missing = |field: &str| format!("authentication result has no {}", field);
+    Ok(Tokens {
+        token_type: authentication_result
+            .token_type
+            .ok_or_else(|| missing("token type"))?,
+        access_token: authentication_result
+            .access_token
+            .ok_or_else(|| missing("access token"))?,
+        refresh_token: authentication_result
+            .refresh_token
+            .or_else(|| refresh_token.map(str::to_string))
+            .ok_or_else(|| missing("refresh token"))?,
+        id_token: authentication_result
+            .id_token
+            .ok_or_else(|| missing("ID token"))?,
+        expires_in: authentication_result.expires_in,
+    })
+}
 
-    let cognito_password_secret_name = env::var("COGNITO_USER_PASSWORD_SECRET_NAME")
-        .expect("COGNITO_USER_PASSWORD_SECRET_NAME not set");
-    let cognito_client_id = env::var("COGNITO_CLIENT_ID").expect("COGNITO_CLIENT_ID not set");
+/// The tokens from an `InitiateAuth` response, which holds a challenge instead when
+/// Cognito wants more from the guest user (e.g. a new password).
+fn authentication_result(response: InitiateAuthOutput) -> Result<AuthenticationResultType, String> {
+    response.authentication_result.ok_or_else(|| {
+        format!(
+            "Cognito answered with challenge {:?} instead of tokens",
+            response.challenge_name
+        )
+    })
+}
 
-    let secrets_manager_client = aws_sdk_secretsmanager::Client::new(&config);
+async fn password_auth(ctx: &AppContext) -> Result<Tokens, Box<dyn std::error::Error>> {
+    let cognito_password_secret_name =
+        ctx.config.require(Setting::CognitoUserPasswordSecretName)?;
+    let cognito_client_id = ctx.config.require(Setting::CognitoClientId)?;
 
-    let secret_string = secrets_manager_client
+    let secret_string = ctx
+        .secrets_manager
         .get_secret_value()
         .secret_id(cognito_password_secret_name)
         .send()
         .await?
         .secret_string
-        .expect("secret_string not set");
+        .ok_or("guest user secret has no string value")?;
 
-    let username_password_map = serde_json::from_str::<HashMap<String, String>>(&secret_string)?;
+    let mut username_password_map =
+        serde_json::from_str::<HashMap<String, String>>(&secret_string)?;
 
     let username = username_password_map
-        .get("username")
-        .expect("username not set")
-        .to_string();
+        .remove("username")
+        .ok_or("guest user secret has no username")?;
 
     let password = username_password_map
-        .get("password")
-        .expect("password not set")
-        .to_string();
+        .remove("password")
+        .ok_or("guest user secret has no password")?;
 
     let auth_params = HashMap::from([
         ("USERNAME".to_string(), username),
         ("PASSWORD".to_string(), password),
     ]);
-    let cognito_idp_client = aws_sdk_cognitoidentityprovider::Client::new(&config);
 
-    let idp_response = cognito_idp_client
+    let idp_response = ctx
+        .cognito
         .initiate_auth()
         .client_id(cognito_client_id)
         .auth_flow(AuthFlowType::UserPasswordAuth)
@@ -104,23 +140,165 @@ pub(crate) async fn function_handler(
         .send()
         .await?;
 
-    let authentication_result = idp_response
-        .authentication_result
-        .expect("authentication result not set");
-    let tokens = Tokens {
-        token_type: authentication_result.token_type.unwrap(),
-        access_token: authentication_result.access_token.unwrap(),
-        refresh_token: authentication_result.refresh_token.unwrap(),
-        id_token: authentication_result.id_token.unwrap(),
-        expires_in: authentication_result.expires_in,
+    let authentication_result = authentication_result(idp_response)?;
+
+    Ok(tokens_from_result(authentication_result, None)?)
+}
+
+/// Exchanges a refresh token for new access and ID tokens. Returns `None` when Cognito
+/// rejects the refresh token as expired or revoked.
+async fn refresh_auth(
+    ctx: &AppContext,
+    refresh_token: &str,
+) -> Result<Option<Tokens>, Box<dyn std::error::Error>> {
+    let cognito_client_id = ctx.config.require(Setting::CognitoClientId)?;
+
+    let auth_params = HashMap::from([("REFRESH_TOKEN".to_string(), refresh_token.to_string())]);
+
+    let idp_response = match ctx
+        .cognito
+        .initiate_auth()
+        .client_id(cognito_client_id)
+        .auth_flow(AuthFlowType::RefreshTokenAuth)
+        .set_auth_parameters(Some(auth_params))
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|e| e.is_not_authorized_exception()) =>
+        {
+            return Ok(None)
+        }
+        Err(err) => return Err(err.into()),
     };
 
-    let tokens_json = serde_json::to_string(&tokens).unwrap();
-    store_tokens(tokens_json.clone()).await;
+    let authentication_result = authentication_result(idp_response)?;
 
-    Ok(ApiGatewayProxyResponse {
-        status_code: 200,
-        body: Some(Body::Text(tokens_json)),
-        ..Default::default()
-    })
+    Ok(Some(tokens_from_result(
+        authentication_result,
+        Some(refresh_token),
+    )?))
+}
+
+async fn new_guest_session(
+    ctx: &AppContext,
+    stale: Option<Tokens>,
+) -> Result<(Tokens, i64), Box<dyn std::error::Error>> {
+    if let Some(stale) = stale {
+        info!("Cached token expiring, refreshing guest session");
+        match refresh_auth(ctx, &stale.refresh_token).await {
+            Ok(Some(tokens)) => {
+                let expires_in = tokens.expires_in.into();
+                return Ok((tokens, expires_in));
+            }
+            Ok(None) => info!("Guest refresh token rejected"),
+            Err(err) => warn!("Guest session refresh failed: {:?}", err),
+        }
+    } else {
+        info!("No cached token found");
+    }
+
+    let tokens = password_auth(ctx).await?;
+    let expires_in = tokens.expires_in.into();
+    Ok((tokens, expires_in))
+}
+
+async fn guest_tokens(
+    ctx: &AppContext,
+    cache: &GuestTokenCache,
+) -> Result<Tokens, Box<dyn std::error::Error>> {
+    let cached = cache
+        .get_or_refresh(|stale| new_guest_session(ctx, stale))
+        .await?;
+
+    // Report the remaining lifetime rather than the one Cognito gave at issue time.
+    let mut tokens = cached.value;
+    let remaining = cached.expires_at - Utc::now().timestamp();
+    tokens.expires_in = remaining.clamp(0, i32::MAX.into()) as i32;
+    Ok(tokens)
+}
+
+fn self_signed_response(
+    issuer: &SelfSignedIssuer,
+    request: &ApiRequest,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error>> {
+    if request
+        .path
+        .as_deref()
+        .is_some_and(|path| path.ends_with(JWKS_PATH))
+    {
+        let mut response = json_response(&issuer.jwks())?;
+        response.headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=3600"),
+        );
+        return Ok(response);
+    }
+
+    if request.http_method == Method::POST {
+        return Ok(error_response(
+            400,
+            "Self-signed guest tokens cannot be refreshed",
+        ));
+    }
+
+    // Callers without a source IP would all share one bucket and throttle each other.
+    match request.source_ip.as_deref() {
+        Some(source_ip) => {
+            if let Err(retry_after) = issuer.check_rate(source_ip) {
+                warn!("Guest token rate limit exceeded for {}", source_ip);
+                let mut response = error_response(429, "Too many requests");
+                response
+                    .headers
+                    .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+                return Ok(response);
+            }
+        }
+        None => warn!("Issuing a guest token without rate limiting: no source IP"),
+    }
+
+    json_response(&issuer.issue()?)
+}
+
+pub(crate) async fn function_handler(
+    ctx: &AppContext,
+    issuer: &GuestIssuer,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error>> {
+    let request = event.payload;
+
+    let cache = match issuer {
+        GuestIssuer::Cognito(cache) => cache,
+        GuestIssuer::SelfSigned(issuer) => return self_signed_response(issuer, &request),
+    };
+
+    // POST exchanges a client's refresh token; anything else hands out guest tokens.
+    if request.http_method == Method::POST {
+        let Some(body) = request.body else {
+            return Ok(error_response(400, "Missing body"));
+        };
+        let Ok(refresh_request) = serde_json::from_str::<RefreshRequest>(&body) else {
+            return Ok(error_response(400, "Missing refreshToken"));
+        };
+
+        return match refresh_auth(ctx, &refresh_request.refresh_token).await {
+            Ok(Some(tokens)) => json_response(&tokens),
+            Ok(None) => Ok(error_response(401, "Invalid refresh token")),
+            Err(err) => {
+                warn!("Guest token refresh failed: {}", err);
+                Ok(error_response(502, "Could not refresh tokens"))
+            }
+        };
+    }
+
+    match guest_tokens(ctx, cache).await {
+        Ok(tokens) => json_response(&tokens),
+        Err(err) => {
+            warn!("Guest sign-in failed: {}", err);
+            Ok(error_response(502, "Could not issue guest tokens"))
+        }
+    }
 }
@@ -1,11 +1,41 @@
 use lambda_runtime::{run, service_fn, tracing, Error};
 mod http_handler;
+mod rate_limit;
+mod self_signed;
+mod signing_key;
+mod token_cache;
 
-use http_handler::function_handler;
+use http_handler::{function_handler, GuestIssuer, GuestTokenCache, TOKEN_SAFETY_MARGIN};
+use self_signed::SelfSignedIssuer;
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::http::handle_http_event;
+use token_cache::SystemClock;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
 
-    run(service_fn(function_handler)).await
+    let ctx = AppContext::load(&[]).await?;
+
+    // A configured signing key switches to minting guest tokens locally.
+    let self_signed = ctx.config.get(Setting::GuestSigningKeySecretName).is_some()
+        || ctx.config.get(Setting::GuestSigningKeyFile).is_some();
+
+    let issuer = if self_signed {
+        ctx.config
+            .check(&[Setting::GuestTokenIssuer, Setting::ApiBlogDomain])?;
+        GuestIssuer::SelfSigned(SelfSignedIssuer::load(&ctx).await?)
+    } else {
+        ctx.config.check(&[
+            Setting::CognitoClientId,
+            Setting::CognitoUserPasswordSecretName,
+        ])?;
+        GuestIssuer::Cognito(GuestTokenCache::new(SystemClock, TOKEN_SAFETY_MARGIN))
+    };
+
+    run(service_fn(|event| {
+        handle_http_event(&ctx, event, |event| function_handler(&ctx, &issuer, event))
+    }))
+    .await
 }
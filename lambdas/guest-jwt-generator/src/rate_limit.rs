@@ -0,0 +1,85 @@
+use crate::token_cache::{Clock, SystemClock};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const WINDOW_SECONDS: i64 = 60;
+
+/// Fixed one-minute window counter keyed by client, e.g. source IP.
+///
+/// State lives in the lambda instance, so the effective limit scales with concurrency;
+/// it is meant to blunt scripted token farming, not to be an exact quota.
+pub(crate) struct RateLimiter<C = SystemClock> {
+    clock: C,
+    per_minute: u32,
+    windows: Mutex<HashMap<String, (i64, u32)>>,
+}
+
+impl<C: Clock> RateLimiter<C> {
+    pub fn new(clock: C, per_minute: u32) -> Self {
+        RateLimiter {
+            clock,
+            per_minute,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an attempt by `key`. Returns the seconds until it may retry if the
+    /// attempt is over the limit.
+    pub fn check(&self, key: &str) -> Result<(), i64> {
+        let now = self.clock.now();
+        let window_start = now - now.rem_euclid(WINDOW_SECONDS);
+
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        // Drop finished windows so idle clients do not accumulate.
+        windows.retain(|_, (start, _)| *start == window_start);
+
+        let (_, count) = windows.entry(key.to_string()).or_insert((window_start, 0));
+        if *count >= self.per_minute {
+            return Err(window_start + WINDOW_SECONDS - now);
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_cache::tests::MockClock;
+
+    #[test]
+    fn allows_the_limit_then_reports_the_rest_of_the_window() {
+        let clock = MockClock::default();
+        let limiter = RateLimiter::new(clock.clone(), 2);
+
+        clock.advance(15);
+        assert_eq!(limiter.check("10.0.0.1"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.1"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.1"), Err(45));
+
+        clock.advance(44);
+        assert_eq!(limiter.check("10.0.0.1"), Err(1));
+    }
+
+    #[test]
+    fn counts_each_key_separately() {
+        let limiter = RateLimiter::new(MockClock::default(), 1);
+        assert_eq!(limiter.check("10.0.0.1"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.2"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.1"), Err(60));
+    }
+
+    #[test]
+    fn starts_over_in_the_next_window() {
+        let clock = MockClock::default();
+        let limiter = RateLimiter::new(clock.clone(), 1);
+
+        clock.advance(59);
+        assert_eq!(limiter.check("10.0.0.1"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.1"), Err(1));
+
+        clock.advance(1);
+        assert_eq!(limiter.check("10.0.0.1"), Ok(()));
+        assert_eq!(limiter.check("10.0.0.1"), Err(60));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::rate_limit::RateLimiter;
+use crate::signing_key::SigningKey;
+use crate::token_cache::SystemClock;
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use serde::Serialize;
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::jwt::GUEST_CLIENT_ID;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+struct GuestClaims<'a> {
+    iss: &'a str,
+    sub: String,
+    client_id: &'a str,
+    token_use: &'a str,
+    scope: &'a str,
+    iat: i64,
+    exp: i64,
+    jti: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GuestToken {
+    #[serde(rename = "accessToken")]
+    access_token: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: i64,
+    #[serde(rename = "tokenType")]
+    token_type: &'static str,
+}
+
+/// Mints guest access tokens locally, so anonymous readers never cost a Cognito call.
+///
+/// Tokens carry only the `guest.read` scope and are verified against the JWKS this
+/// lambda publishes.
+pub(crate) struct SelfSignedIssuer {
+    key: SigningKey,
+    issuer: String,
+    scope: String,
+    ttl: i64,
+    limiter: RateLimiter,
+}
+
+impl SelfSignedIssuer {
+    /// Reads the signing key from Secrets Manager, or from a file when no secret is set.
+    pub async fn load(ctx: &AppContext) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config = &ctx.config;
+
+        let pem = match (
+            config.get(Setting::GuestSigningKeySecretName),
+            config.get(Setting::GuestSigningKeyFile),
+        ) {
+            (Some(secret_name), _) => ctx
+                .secrets_manager
+                .get_secret_value()
+                .secret_id(secret_name)
+                .send()
+                .await?
+                .secret_string
+                .ok_or("guest signing key secret has no string value")?,
+            (None, Some(path)) => std::fs::read_to_string(path)?,
+            (None, None) => return Err("no guest signing key configured".into()),
+        };
+        let key = SigningKey::from_pem(&pem)?;
+
+        let issuer = config.require(Setting::GuestTokenIssuer)?.to_string();
+        let scope = format!("https://{}/guest.read", config.api_blog_domain()?);
+        info!("Signing guest tokens for {} locally", issuer);
+
+        Ok(SelfSignedIssuer {
+            key,
+            issuer,
+            scope,
+            ttl: config.limits.guest_token_ttl,
+            limiter: RateLimiter::new(SystemClock, config.limits.guest_tokens_per_minute),
+        })
+    }
+
+    /// Counts an issuance against `source_ip`, returning the seconds to wait when it
+    /// is over the limit.
+    pub fn check_rate(&self, source_ip: &str) -> Result<(), i64> {
+        self.limiter.check(source_ip)
+    }
+
+    pub fn issue(&self) -> Result<GuestToken, jsonwebtoken::errors::Error> {
+        let now = Utc::now().timestamp();
+        let claims = GuestClaims {
+            iss: &self.issuer,
+            sub: format!("guest-{}", Uuid::new_v4()),
+            client_id: GUEST_CLIENT_ID,
+            token_use: "access",
+            scope: &self.scope,
+            iat: now,
+            exp: now + self.ttl,
+            jti: Uuid::new_v4().to_string(),
+        };
+
+        Ok(GuestToken {
+            access_token: self.key.sign(&claims)?,
+            expires_in: self.ttl,
+            token_type: "Bearer",
+        })
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        self.key.jwks()
+    }
+}
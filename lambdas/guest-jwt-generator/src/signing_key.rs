@@ -0,0 +1,246 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents, ECDSA_P256_SHA256_FIXED_SIGNING,
+};
+use serde::Serialize;
+
+/// A private key for signing guest tokens, along with the public JWK that verifies them.
+///
+/// Accepts RSA keys in PKCS#1 or PKCS#8 PEM (signed with RS256) and P-256 keys in
+/// PKCS#8 PEM (signed with ES256). The key id is the key's RFC 7638 thumbprint, so it
+/// stays stable across cold starts without being configured.
+pub(crate) struct SigningKey {
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    jwk: Jwk,
+}
+
+impl SigningKey {
+    pub fn from_pem(pem: &str) -> Result<Self, String> {
+        let parsed = pem::parse(pem).map_err(|e| format!("invalid PEM: {}", e))?;
+        let der = parsed.contents();
+
+        match parsed.tag() {
+            "RSA PRIVATE KEY" => {
+                let key_pair = RsaKeyPair::from_der(der).map_err(|e| e.to_string())?;
+                Self::rsa(&key_pair, EncodingKey::from_rsa_der(der))
+            }
+            "PRIVATE KEY" => {
+                if let Ok(key_pair) = RsaKeyPair::from_pkcs8(der) {
+                    let encoding_key =
+                        EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+                    return Self::rsa(&key_pair, encoding_key);
+                }
+                let key_pair = EcdsaKeyPair::from_pkcs8(
+                    &ECDSA_P256_SHA256_FIXED_SIGNING,
+                    der,
+                    &SystemRandom::new(),
+                )
+                .map_err(|_| "expected an RSA or P-256 private key".to_string())?;
+                Self::ec(&key_pair, EncodingKey::from_ec_der(der))
+            }
+            "EC PRIVATE KEY" => Err(
+                "SEC1 EC keys are not supported; convert with `openssl pkcs8 -topk8 -nocrypt`"
+                    .to_string(),
+            ),
+            tag => Err(format!("unsupported PEM block {:?}", tag)),
+        }
+    }
+
+    fn rsa(key_pair: &RsaKeyPair, encoding_key: EncodingKey) -> Result<Self, String> {
+        let components: RsaPublicKeyComponents<Vec<u8>> = key_pair.public().into();
+        let n = URL_SAFE_NO_PAD.encode(&components.n);
+        let e = URL_SAFE_NO_PAD.encode(&components.e);
+
+        #[derive(Serialize)]
+        struct Thumbprint<'a> {
+            e: &'a str,
+            kty: &'a str,
+            n: &'a str,
+        }
+        let kid = thumbprint(&Thumbprint {
+            e: &e,
+            kty: "RSA",
+            n: &n,
+        })?;
+
+        Ok(SigningKey {
+            algorithm: Algorithm::RS256,
+            encoding_key,
+            jwk: Jwk {
+                common: common_parameters(kid, KeyAlgorithm::RS256),
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n,
+                    e,
+                }),
+            },
+        })
+    }
+
+    fn ec(key_pair: &EcdsaKeyPair, encoding_key: EncodingKey) -> Result<Self, String> {
+        // Uncompressed SEC1 point: 0x04 || x || y.
+        let point = key_pair.public_key().as_ref();
+        let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+        let x = URL_SAFE_NO_PAD.encode(x);
+        let y = URL_SAFE_NO_PAD.encode(y);
+
+        #[derive(Serialize)]
+        struct Thumbprint<'a> {
+            crv: &'a str,
+            kty: &'a str,
+            x: &'a str,
+            y: &'a str,
+        }
+        let kid = thumbprint(&Thumbprint {
+            crv: "P-256",
+            kty: "EC",
+            x: &x,
+            y: &y,
+        })?;
+
+        Ok(SigningKey {
+            algorithm: Algorithm::ES256,
+            encoding_key,
+            jwk: Jwk {
+                common: common_parameters(kid, KeyAlgorithm::ES256),
+                algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x,
+                    y,
+                }),
+            },
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.jwk.common.key_id.clone();
+        encode(&header, claims, &self.encoding_key)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: vec![self.jwk.clone()],
+        }
+    }
+}
+
+fn common_parameters(kid: String, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid),
+        ..Default::default()
+    }
+}
+
+/// RFC 7638 thumbprint: the SHA-256 of the required members, serialized in
+/// lexicographic order without whitespace. Callers pass a struct whose fields are
+/// declared in that order.
+fn thumbprint<T: Serialize>(members: &T) -> Result<String, String> {
+    let json = serde_json::to_vec(members).map_err(|e| e.to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(digest(&SHA256, &json)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+    use serde_json::{json, Value};
+    use std::path::Path;
+
+    fn pem(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn key_id(key: &SigningKey) -> &str {
+        key.jwk.common.key_id.as_deref().unwrap()
+    }
+
+    #[test]
+    fn reads_rsa_keys_in_either_encoding() {
+        let pkcs1 = SigningKey::from_pem(&pem("rsa_pkcs1.pem")).unwrap();
+        let pkcs8 = SigningKey::from_pem(&pem("rsa_pkcs8.pem")).unwrap();
+
+        assert_eq!(pkcs1.algorithm, Algorithm::RS256);
+        assert_eq!(pkcs8.algorithm, Algorithm::RS256);
+        assert!(matches!(pkcs1.jwk.algorithm, AlgorithmParameters::RSA(_)));
+        // Same key, same thumbprint.
+        assert_eq!(key_id(&pkcs1), key_id(&pkcs8));
+    }
+
+    #[test]
+    fn reads_p256_pkcs8_keys() {
+        let key = SigningKey::from_pem(&pem("ec_pkcs8.pem")).unwrap();
+        assert_eq!(key.algorithm, Algorithm::ES256);
+        match &key.jwk.algorithm {
+            AlgorithmParameters::EllipticCurve(params) => {
+                assert_eq!(params.curve, EllipticCurve::P256)
+            }
+            other => panic!("expected an EC key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_sec1_and_other_blocks() {
+        let err = SigningKey::from_pem(&pem("ec_sec1.pem")).err().unwrap();
+        assert!(err.contains("openssl pkcs8"), "{}", err);
+
+        let public = "-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n";
+        assert!(SigningKey::from_pem(public).is_err());
+        assert!(SigningKey::from_pem("not a key").is_err());
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc_7638() {
+        #[derive(Serialize)]
+        struct Thumbprint<'a> {
+            e: &'a str,
+            kty: &'a str,
+            n: &'a str,
+        }
+        // The example key from RFC 7638 section 3.1.
+        let members = Thumbprint {
+            e: "AQAB",
+            kty: "RSA",
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+        };
+        assert_eq!(
+            thumbprint(&members).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn signed_tokens_verify_against_the_published_jwks() {
+        for name in ["rsa_pkcs1.pem", "rsa_pkcs8.pem", "ec_pkcs8.pem"] {
+            let key = SigningKey::from_pem(&pem(name)).unwrap();
+            let claims = json!({ "sub": "guest", "exp": chrono::Utc::now().timestamp() + 60 });
+            let token = key.sign(&claims).unwrap();
+
+            let header = jsonwebtoken::decode_header(&token).unwrap();
+            let jwks = key.jwks();
+            let jwk = jwks.find(header.kid.as_deref().unwrap()).unwrap();
+            let decoded = decode::<Value>(
+                &token,
+                &DecodingKey::from_jwk(jwk).unwrap(),
+                &Validation::new(key.algorithm),
+            )
+            .unwrap();
+            assert_eq!(decoded.claims["sub"], "guest", "{}", name);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::Utc;
+use std::future::Future;
+use tokio::sync::{Mutex, RwLock};
+
+pub(crate) trait Clock {
+    /// Current time as Unix epoch seconds.
+    fn now(&self) -> i64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        Utc::now().timestamp()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Cached<T> {
+    pub value: T,
+    pub expires_at: i64,
+}
+
+/// Caches a value that expires after a lifetime reported by whoever produced it.
+///
+/// Values are treated as stale `safety_margin` seconds before they actually expire, so
+/// callers never hand out a token that lapses in flight. Concurrent callers that find
+/// the value stale share a single refresh.
+#[derive(Debug)]
+pub(crate) struct TokenCache<T, C = SystemClock> {
+    clock: C,
+    safety_margin: i64,
+    entry: RwLock<Option<Cached<T>>>,
+    refresh_lock: Mutex<()>,
+}
+
+impl<T: Clone, C: Clock> TokenCache<T, C> {
+    pub fn new(clock: C, safety_margin: i64) -> Self {
+        TokenCache {
+            clock,
+            safety_margin,
+            entry: RwLock::new(None),
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the cached value if it is still fresh.
+    pub async fn get(&self) -> Option<Cached<T>> {
+        let now = self.clock.now();
+        self.entry
+            .read()
+            .await
+            .as_ref()
+            .filter(|cached| self.is_fresh(cached, now))
+            .cloned()
+    }
+
+    /// Returns the cached value, or runs `refresh` to replace it once it is stale.
+    ///
+    /// `refresh` receives the stale value, if any, and returns the new value together
+    /// with its lifetime in seconds. Errors are passed through and leave the cache as-is.
+    pub async fn get_or_refresh<F, Fut, E>(&self, refresh: F) -> Result<Cached<T>, E>
+    where
+        F: FnOnce(Option<T>) -> Fut,
+        Fut: Future<Output = Result<(T, i64), E>>,
+    {
+        if let Some(cached) = self.get().await {
+            return Ok(cached);
+        }
+
+        let _guard = self.refresh_lock.lock().await;
+
+        // Another caller may have refreshed while we waited for the lock.
+        if let Some(cached) = self.get().await {
+            return Ok(cached);
+        }
+
+        let stale = self
+            .entry
+            .read()
+            .await
+            .as_ref()
+            .map(|cached| cached.value.clone());
+
+        let (value, expires_in) = refresh(stale).await?;
+
+        let cached = Cached {
+            value,
+            expires_at: self.clock.now() + expires_in,
+        };
+        *self.entry.write().await = Some(cached.clone());
+
+        Ok(cached)
+    }
+
+    fn is_fresh(&self, cached: &Cached<T>, now: i64) -> bool {
+        cached.expires_at - self.safety_margin > now
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// A clock that only moves when told to, starting at the epoch.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct MockClock(Arc<AtomicI64>);
+
+    impl MockClock {
+        pub(crate) fn advance(&self, seconds: i64) {
+            self.0.fetch_add(seconds, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> i64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn refresh_to(
+        value: &'static str,
+        expires_in: i64,
+    ) -> Result<(&'static str, i64), String> {
+        Ok((value, expires_in))
+    }
+
+    #[tokio::test]
+    async fn keeps_value_until_safety_margin_before_expiry() {
+        let clock = MockClock::default();
+        let cache = TokenCache::new(clock.clone(), 60);
+
+        let first = cache
+            .get_or_refresh(|_| refresh_to("first", 3600))
+            .await
+            .unwrap();
+        assert_eq!(first.value, "first");
+        assert_eq!(first.expires_at, 3600);
+
+        clock.advance(3539);
+        let cached = cache
+            .get_or_refresh(|_| refresh_to("second", 3600))
+            .await
+            .unwrap();
+        assert_eq!(cached.value, "first");
+
+        clock.advance(1);
+        assert!(cache.get().await.is_none());
+        let refreshed = cache
+            .get_or_refresh(|_| refresh_to("second", 3600))
+            .await
+            .unwrap();
+        assert_eq!(refreshed.value, "second");
+        assert_eq!(refreshed.expires_at, 3540 + 3600);
+    }
+
+    #[tokio::test]
+    async fn uses_lifetime_reported_by_refresh() {
+        let clock = MockClock::default();
+        let cache = TokenCache::new(clock.clone(), 60);
+
+        cache
+            .get_or_refresh(|_| refresh_to("short", 300))
+            .await
+            .unwrap();
+
+        clock.advance(240);
+        assert!(cache.get().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn passes_stale_value_to_refresh() {
+        let clock = MockClock::default();
+        let cache = TokenCache::new(clock.clone(), 0);
+
+        cache
+            .get_or_refresh(|stale| {
+                assert!(stale.is_none());
+                refresh_to("first", 10)
+            })
+            .await
+            .unwrap();
+
+        clock.advance(10);
+        cache
+            .get_or_refresh(|stale| {
+                assert_eq!(stale, Some("first"));
+                refresh_to("second", 10)
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_is_retried_on_next_call() {
+        let clock = MockClock::default();
+        let cache: TokenCache<&str, _> = TokenCache::new(clock, 0);
+
+        let err = cache
+            .get_or_refresh(|_| async { Err::<(&str, i64), _>("cognito unavailable") })
+            .await
+            .unwrap_err();
+        assert_eq!(err, "cognito unavailable");
+        assert!(cache.get().await.is_none());
+
+        let cached = cache
+            .get_or_refresh(|_| refresh_to("recovered", 10))
+            .await
+            .unwrap();
+        assert_eq!(cached.value, "recovered");
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_refresh() {
+        let cache = Arc::new(TokenCache::new(MockClock::default(), 0));
+        let refreshes = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let refreshes = refreshes.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_refresh(|_| async move {
+                            refreshes.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            Ok::<_, String>(("shared", 60))
+                        })
+                        .await
+                        .unwrap()
+                        .value
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), "shared");
+        }
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,21 +1,55 @@
+use crate::scope_map::ScopeMap;
 use aws_lambda_events::cognito::{
     ClaimsAndScopeOverrideDetailsV2, CognitoAccessTokenGenerationV2,
     CognitoEventUserPoolsPreTokenGenResponseV2, CognitoEventUserPoolsPreTokenGenV2,
+    CognitoIdTokenGenerationV2,
 };
 use lambda_runtime::LambdaEvent;
+use shared::context::AppContext;
+use shared::db::get_user;
 use std::collections::{HashMap, HashSet};
-use std::env;
-use tracing::info;
+use tracing::{info, warn};
 
-fn get_group_key(group: &str, stage: &str) -> String {
-    if stage == "Prod" {
-        group.to_string()
-    } else {
-        format!("{}+{}", group, stage)
+/// Claims copied from the user's row in the users table into both tokens, named as
+/// `shared::models::Claims` reads them back.
+async fn user_claims(ctx: &AppContext, sub: &str) -> HashMap<String, String> {
+    let mut claims = HashMap::new();
+
+    let Some(users_table) = ctx.config.users_table.as_deref() else {
+        return claims;
+    };
+
+    // A missing or unreadable profile should not stop the user from signing in.
+    let user = match get_user(&ctx.dynamodb, users_table, sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            info!("No users table entry for {}", sub);
+            return claims;
+        }
+        Err(err) => {
+            warn!("Could not load user {}: {}", sub, err);
+            return claims;
+        }
+    };
+
+    if let Some(author_id) = user.author_id {
+        claims.insert("blog:author_id".to_string(), author_id);
+    }
+    if let Some(display_name) = user.display_name {
+        claims.insert("blog:author_name".to_string(), display_name);
+    }
+    if !user.roles.is_empty() {
+        claims.insert("blog:roles".to_string(), user.roles.join(","));
     }
+    if let Some(blog_id) = user.blog_id {
+        claims.insert("blog:blog_id".to_string(), blog_id);
+    }
+    claims
 }
 
 pub(crate) async fn function_handler(
+    ctx: &AppContext,
+    scope_map: &ScopeMap,
     event: LambdaEvent<CognitoEventUserPoolsPreTokenGenV2>,
 ) -> Result<CognitoEventUserPoolsPreTokenGenV2, Box<dyn std::error::Error>> {
     info!("Full event: {:?}", event);
@@ -23,51 +57,11 @@ pub(crate) async fn function_handler(
     let request = event.payload.request;
     info!("Pre Token Generation Triggered: {:?}", request);
 
-    let stage = env::var("STAGE").expect("STAGE is not set");
-
-    let api_blog_domain = env::var("API_BLOG_DOMAIN").expect("API_BLOG_DOMAIN is not set");
-    let api_blog_domain_url = format!("https://{}", api_blog_domain);
-
-    let group_to_scope_map: HashMap<String, Vec<String>> = HashMap::from([
-        (
-            get_group_key("blog-admin", &stage),
-            vec![
-                format!("{}/admin.read", api_blog_domain_url),
-                format!("{}/admin.write", api_blog_domain_url),
-                format!("{}/admin.delete", api_blog_domain_url),
-            ],
-        ),
-        (
-            get_group_key("blog-author", &stage),
-            vec![
-                format!("{}/author.read", api_blog_domain_url),
-                format!("{}/author.write", api_blog_domain_url),
-                format!("{}/author.delete", api_blog_domain_url),
-            ],
-        ),
-        (
-            get_group_key("blog-commenter", &stage),
-            vec![
-                format!("{}/commenter.read", api_blog_domain_url),
-                format!("{}/commenter.write", api_blog_domain_url),
-                format!("{}/commenter.delete", api_blog_domain_url),
-            ],
-        ),
-        (
-            get_group_key("blog-guest", &stage),
-            vec![
-                format!("{}/guest.read", api_blog_domain_url),
-                format!("{}/guest.write", api_blog_domain_url),
-                format!("{}/guest.delete", api_blog_domain_url),
-            ],
-        ),
-    ]);
-
     let mut assigned_scopes: HashSet<String> = HashSet::new();
     assigned_scopes.insert("aws.cognito.signin.user.admin".to_string());
 
     for group in request.group_configuration.groups_to_override.clone() {
-        if let Some(scopes) = group_to_scope_map.get(group.as_str()) {
+        if let Some(scopes) = scope_map.scopes_for(&group) {
             assigned_scopes.extend(scopes.iter().cloned())
         }
     }
@@ -78,9 +72,19 @@ pub(crate) async fn function_handler(
         .cloned()
         .collect();
 
+    let added_claims = match request.user_attributes.get("sub") {
+        Some(sub) => user_claims(ctx, sub).await,
+        None => HashMap::new(),
+    };
+
     let response = CognitoEventUserPoolsPreTokenGenResponseV2 {
         claims_and_scope_override_details: Some(ClaimsAndScopeOverrideDetailsV2 {
+            id_token_generation: Some(CognitoIdTokenGenerationV2 {
+                claims_to_add_or_override: added_claims.clone(),
+                ..Default::default()
+            }),
             access_token_generation: Some(CognitoAccessTokenGenerationV2 {
+                claims_to_add_or_override: added_claims,
                 scopes_to_add: assigned_scopes.into_iter().collect(),
                 scopes_to_suppress: suppressed_scopes,
                 ..Default::default()
@@ -1,11 +1,21 @@
 use lambda_runtime::{run, service_fn, tracing, Error};
 mod event_handler;
+mod scope_map;
 
 use event_handler::function_handler;
+use scope_map::ScopeMap;
+use shared::config::Setting;
+use shared::context::AppContext;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
 
-    run(service_fn(function_handler)).await
+    let ctx = AppContext::load(&[Setting::Stage, Setting::ApiBlogDomain]).await?;
+    let scope_map = ScopeMap::load(&ctx.config).await?;
+
+    run(service_fn(|event| {
+        function_handler(&ctx, &scope_map, event)
+    }))
+    .await
 }
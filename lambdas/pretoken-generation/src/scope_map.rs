@@ -0,0 +1,440 @@
+use serde::Deserialize;
+use shared::config::{Config, Setting};
+use shared::models::PROD_STAGE;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use tracing::info;
+
+/// Used when no mapping is configured.
+const BUNDLED_SCOPE_MAP: &str = include_str!("../scope-map.json");
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupDefinition {
+    /// Scope names relative to the blog API (`author.write`), or full scope strings
+    /// when they contain a `/`.
+    #[serde(default)]
+    scopes: Vec<String>,
+    /// Groups whose scopes this group also receives.
+    #[serde(default)]
+    inherits: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageOverrides {
+    /// Replaces or adds group definitions for this stage.
+    #[serde(default)]
+    groups: HashMap<String, GroupDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScopeMapDocument {
+    groups: HashMap<String, GroupDefinition>,
+    #[serde(default)]
+    stages: HashMap<String, StageOverrides>,
+}
+
+/// Scopes granted to each Cognito group, resolved for one stage.
+#[derive(Debug)]
+pub(crate) struct ScopeMap {
+    stage: String,
+    scopes: HashMap<String, BTreeSet<String>>,
+}
+
+impl ScopeMap {
+    /// Loads the mapping from `SCOPE_MAP`, `SCOPE_MAP_PARAMETER` or `SCOPE_MAP_FILE`,
+    /// in that order, falling back to the bundled `scope-map.json`.
+    pub async fn load(config: &Config) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let (source, document) = if let Some(inline) = config.get(Setting::ScopeMap) {
+            (
+                Setting::ScopeMap.env_var().to_string(),
+                parse_document(inline)?,
+            )
+        } else if let Some(name) = config.get(Setting::ScopeMapParameter) {
+            (
+                format!("parameter {}", name),
+                parse_document(&fetch_parameter(name).await?)?,
+            )
+        } else if let Some(path) = config.get(Setting::ScopeMapFile) {
+            (path.to_string(), parse_file(Path::new(path))?)
+        } else {
+            (
+                "bundled scope-map.json".to_string(),
+                parse_json(BUNDLED_SCOPE_MAP)?,
+            )
+        };
+
+        let scope_map = Self::resolve(document, config.stage()?, config.api_blog_domain()?)
+            .map_err(|e| format!("invalid scope map from {}: {}", source, e))?;
+        info!(
+            "Loaded scopes for {} groups from {}",
+            scope_map.scopes.len(),
+            source
+        );
+        Ok(scope_map)
+    }
+
+    /// Applies the stage's overrides, then flattens inheritance, rejecting unknown
+    /// groups and cycles.
+    fn resolve(
+        mut document: ScopeMapDocument,
+        stage: &str,
+        api_blog_domain: &str,
+    ) -> Result<Self, String> {
+        if let Some(overrides) = document.stages.remove(stage) {
+            document.groups.extend(overrides.groups);
+        }
+
+        let resource_server = format!("https://{}", api_blog_domain);
+        let mut scopes = HashMap::new();
+        for group in document.groups.keys() {
+            let mut resolved = BTreeSet::new();
+            collect_scopes(
+                &document.groups,
+                group,
+                &resource_server,
+                &mut Vec::new(),
+                &mut resolved,
+            )?;
+            scopes.insert(group.clone(), resolved);
+        }
+
+        Ok(ScopeMap {
+            stage: stage.to_string(),
+            scopes,
+        })
+    }
+
+    /// Returns the scopes for a Cognito group name. Outside `Prod`, groups are named
+    /// `<group>+<stage>` and only those for the current stage match.
+    pub fn scopes_for(&self, cognito_group: &str) -> Option<&BTreeSet<String>> {
+        let group = if self.stage == PROD_STAGE {
+            cognito_group
+        } else {
+            cognito_group.strip_suffix(&format!("+{}", self.stage))?
+        };
+        self.scopes.get(group)
+    }
+}
+
+fn collect_scopes(
+    groups: &HashMap<String, GroupDefinition>,
+    group: &str,
+    resource_server: &str,
+    path: &mut Vec<String>,
+    resolved: &mut BTreeSet<String>,
+) -> Result<(), String> {
+    if path.iter().any(|g| g == group) {
+        path.push(group.to_string());
+        return Err(format!("inheritance cycle {}", path.join(" -> ")));
+    }
+    let definition = groups.get(group).ok_or_else(|| match path.last() {
+        Some(parent) => format!("{} inherits unknown group {}", parent, group),
+        None => format!("unknown group {}", group),
+    })?;
+
+    for scope in &definition.scopes {
+        let scope = scope.trim();
+        if scope.is_empty() || scope.contains(char::is_whitespace) {
+            return Err(format!("{} has an invalid scope {:?}", group, scope));
+        }
+        resolved.insert(if scope.contains('/') {
+            scope.to_string()
+        } else {
+            format!("{}/{}", resource_server, scope)
+        });
+    }
+
+    path.push(group.to_string());
+    for parent in &definition.inherits {
+        collect_scopes(groups, parent, resource_server, path, resolved)?;
+    }
+    path.pop();
+    Ok(())
+}
+
+fn parse_json(contents: &str) -> Result<ScopeMapDocument, String> {
+    serde_json::from_str(contents).map_err(|e| e.to_string())
+}
+
+fn parse_yaml(contents: &str) -> Result<ScopeMapDocument, String> {
+    serde_yaml::from_str(contents).map_err(|e| e.to_string())
+}
+
+/// Inline and Parameter Store documents may be JSON or YAML. A document that looks
+/// like JSON reports the JSON error, anything else the YAML one.
+fn parse_document(contents: &str) -> Result<ScopeMapDocument, String> {
+    parse_json(contents).or_else(|json_error| {
+        if contents.trim_start().starts_with('{') {
+            Err(json_error)
+        } else {
+            parse_yaml(contents)
+        }
+    })
+}
+
+fn parse_file(path: &Path) -> Result<ScopeMapDocument, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json(&contents),
+        Some("yaml" | "yml") => parse_yaml(&contents),
+        Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+        _ => Err("expected a .json, .yaml, .yml or .toml file".to_string()),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[derive(Debug, Deserialize)]
+struct ParameterResponse {
+    #[serde(rename = "Parameter")]
+    parameter: Parameter,
+}
+
+#[derive(Debug, Deserialize)]
+struct Parameter {
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+/// Reads a Parameter Store value through the AWS Parameters and Secrets Lambda
+/// extension, which must be attached to the function as a layer.
+async fn fetch_parameter(name: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let port = std::env::var("PARAMETERS_SECRETS_EXTENSION_HTTP_PORT")
+        .unwrap_or_else(|_| "2773".to_string());
+    let session_token = std::env::var("AWS_SESSION_TOKEN")?;
+
+    let response: ParameterResponse = reqwest::Client::new()
+        .get(format!(
+            "http://localhost:{}/systemsmanager/parameters/get",
+            port
+        ))
+        .query(&[("name", name), ("withDecryption", "true")])
+        .header("X-Aws-Parameters-Secrets-Token", session_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response.parameter.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMAIN: &str = "api.example.com";
+
+    fn scope_set(scopes: &[&str]) -> BTreeSet<String> {
+        scopes
+            .iter()
+            .map(|scope| {
+                if scope.contains('/') {
+                    scope.to_string()
+                } else {
+                    format!("https://{}/{}", DOMAIN, scope)
+                }
+            })
+            .collect()
+    }
+
+    fn resolve(document: &str, stage: &str) -> Result<ScopeMap, String> {
+        ScopeMap::resolve(parse_document(document)?, stage, DOMAIN)
+    }
+
+    fn testdata(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    #[test]
+    fn resolves_inherited_and_overridden_scopes() {
+        let document = r#"{
+            "groups": {
+                "blog-admin": {"scopes": ["admin.write"], "inherits": ["blog-author"]},
+                "blog-author": {"scopes": ["author.write"], "inherits": ["blog-commenter"]},
+                "blog-commenter": {"scopes": ["commenter.write", "https://other.example.com/read"]},
+                "blog-guest": {"scopes": ["guest.read"]}
+            },
+            "stages": {
+                "Dev": {"groups": {
+                    "blog-guest": {"scopes": ["guest.read", "guest.write"]},
+                    "blog-tester": {"inherits": ["blog-author"]}
+                }}
+            }
+        }"#;
+        let cases: &[(&str, &str, Option<&[&str]>)] = &[
+            (
+                PROD_STAGE,
+                "blog-admin",
+                Some(&[
+                    "admin.write",
+                    "author.write",
+                    "commenter.write",
+                    "https://other.example.com/read",
+                ]),
+            ),
+            (
+                PROD_STAGE,
+                "blog-commenter",
+                Some(&["commenter.write", "https://other.example.com/read"]),
+            ),
+            (PROD_STAGE, "blog-guest", Some(&["guest.read"])),
+            (PROD_STAGE, "blog-tester", None),
+            (
+                "Dev",
+                "blog-guest+Dev",
+                Some(&["guest.read", "guest.write"]),
+            ),
+            (
+                "Dev",
+                "blog-tester+Dev",
+                Some(&[
+                    "author.write",
+                    "commenter.write",
+                    "https://other.example.com/read",
+                ]),
+            ),
+            // Stages without overrides use the base groups.
+            ("Test", "blog-guest+Test", Some(&["guest.read"])),
+        ];
+
+        for (stage, group, expected) in cases {
+            let scope_map = resolve(document, stage).unwrap();
+            assert_eq!(
+                scope_map.scopes_for(group),
+                expected.map(scope_set).as_ref(),
+                "{} in {}",
+                group,
+                stage
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        let cases = [
+            (
+                r#"{"groups": {"a": {"inherits": ["b"]}, "b": {"inherits": ["a"]}}}"#,
+                "inheritance cycle",
+            ),
+            (
+                r#"{"groups": {"a": {"inherits": ["a"]}}}"#,
+                "inheritance cycle a -> a",
+            ),
+            (
+                r#"{"groups": {"a": {"inherits": ["missing"]}}}"#,
+                "a inherits unknown group missing",
+            ),
+            (
+                r#"{"groups": {"a": {"scopes": ["author read"]}}}"#,
+                "a has an invalid scope",
+            ),
+            (
+                r#"{"groups": {"a": {"scopes": [" "]}}}"#,
+                "a has an invalid scope",
+            ),
+            (
+                r#"{"groups": {}, "stages": {"Dev": {"groups": {"a": {"inherits": ["b"]}}}}}"#,
+                "a inherits unknown group b",
+            ),
+        ];
+
+        for (document, expected) in cases {
+            let stage = if document.contains("stages") {
+                "Dev"
+            } else {
+                PROD_STAGE
+            };
+            let err = resolve(document, stage).unwrap_err();
+            assert!(err.contains(expected), "{:?} gave {:?}", document, err);
+        }
+    }
+
+    #[test]
+    fn scopes_for_matches_only_the_current_stage_suffix() {
+        let document = r#"{"groups": {"blog-author": {"scopes": ["author.read"]}}}"#;
+        let cases: &[(&str, &str, bool)] = &[
+            (PROD_STAGE, "blog-author", true),
+            (PROD_STAGE, "blog-author+Dev", false),
+            ("Dev", "blog-author+Dev", true),
+            ("Dev", "blog-author", false),
+            ("Dev", "blog-author+Test", false),
+            ("Dev", "blog-author+dev", false),
+            ("Dev", "blog-author+Dev+Dev", false),
+        ];
+
+        for (stage, group, matches) in cases {
+            let scope_map = resolve(document, stage).unwrap();
+            assert_eq!(
+                scope_map.scopes_for(group).is_some(),
+                *matches,
+                "{} in {}",
+                group,
+                stage
+            );
+        }
+    }
+
+    #[test]
+    fn parses_json_and_yaml_documents() {
+        let yaml = "groups:\n  blog-author:\n    scopes: [author.read]\n";
+        let json = r#"{"groups": {"blog-author": {"scopes": ["author.read"]}}}"#;
+        for document in [yaml, json] {
+            let scope_map = resolve(document, PROD_STAGE).unwrap();
+            assert_eq!(
+                scope_map.scopes_for("blog-author"),
+                Some(&scope_set(&["author.read"]))
+            );
+        }
+
+        // A JSON-looking document reports the JSON error rather than the YAML one.
+        let err = parse_document(r#"{"groups": "#).unwrap_err();
+        assert!(err.contains("EOF"), "{}", err);
+        assert!(parse_document("groups:\n  a:\n    unknown: 1\n").is_err());
+    }
+
+    #[test]
+    fn bundled_scope_map_is_valid() {
+        let scope_map =
+            ScopeMap::resolve(parse_json(BUNDLED_SCOPE_MAP).unwrap(), PROD_STAGE, DOMAIN).unwrap();
+        let admin = scope_map.scopes_for("blog-admin").unwrap();
+        assert!(admin.contains(&format!("https://{}/commenter.write", DOMAIN)));
+    }
+
+    #[test]
+    fn parses_files_by_extension() {
+        for name in ["scope-map.yaml", "scope-map.toml"] {
+            let document = parse_file(&testdata(name)).unwrap();
+            let scope_map = ScopeMap::resolve(document, "Dev", DOMAIN).unwrap();
+            assert_eq!(
+                scope_map.scopes_for("blog-author+Dev"),
+                Some(&scope_set(&[
+                    "author.read",
+                    "author.write",
+                    "commenter.read"
+                ])),
+                "{}",
+                name
+            );
+            assert_eq!(
+                scope_map.scopes_for("blog-tester+Dev"),
+                Some(&scope_set(&["admin.read"])),
+                "{}",
+                name
+            );
+        }
+
+        let bundled = Path::new(env!("CARGO_MANIFEST_DIR")).join("scope-map.json");
+        assert!(parse_file(&bundled).is_ok());
+
+        let err = parse_file(&testdata("scope-map.txt")).unwrap_err();
+        assert!(err.contains("expected a .json"), "{}", err);
+        let err = parse_file(&testdata("missing.json")).unwrap_err();
+        assert!(err.contains("missing.json"), "{}", err);
+    }
+}
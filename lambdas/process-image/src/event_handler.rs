@@ -0,0 +1,146 @@
+use crate::pipeline::{render, source_format};
+use aws_lambda_events::s3::S3Event;
+use aws_sdk_s3::primitives::ByteStream;
+use lambda_runtime::LambdaEvent;
+use shared::context::AppContext;
+use shared::db::{get_content, set_content_variants};
+use shared::models::{ContentStatus, ContentVariant};
+use tracing::{info, warn};
+
+/// User metadata set on an original that process-image has replaced with a copy
+/// without EXIF, so the notification for that copy does not replace it again.
+const STRIPPED_METADATA: &str = "stripped";
+
+/// The content id of an original upload's key (`content/<id>.<ext>`).
+fn content_id(key: &str) -> Option<&str> {
+    let (id, _) = key.strip_prefix("content/")?.rsplit_once('.')?;
+    (!id.is_empty() && !id.contains('/')).then_some(id)
+}
+
+/// Handles S3 `ObjectCreated` notifications for the content bucket.
+///
+/// Errors are returned (so Lambda retries the event) only for failures that may be
+/// transient, such as the content item not having been written or finalized yet.
+pub(crate) async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<S3Event>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let bucket = ctx.config.content_bucket()?;
+
+    for record in event.payload.records {
+        let Some(key) = record.s3.object.key else {
+            continue;
+        };
+        if record.s3.bucket.name.as_deref() != Some(bucket) {
+            warn!("Ignoring object {} from another bucket", key);
+            continue;
+        }
+        let Some(content_id) = content_id(&key) else {
+            info!("Ignoring object {}", key);
+            continue;
+        };
+        process(ctx, bucket, content_id, &key).await?;
+    }
+
+    Ok(())
+}
+
+async fn process(
+    ctx: &AppContext,
+    bucket: &str,
+    content_id: &str,
+    key: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let table_name = ctx.config.content_table()?;
+    let pk = format!("CONTENT#{}", content_id);
+
+    // create-content stores the object before it writes the item.
+    let content = get_content(&ctx.dynamodb, table_name, &pk)
+        .await?
+        .ok_or_else(|| format!("Content {} not found", content_id))?;
+
+    // finalize-upload copies an upload into `content/` just before it marks it ready.
+    if content.status == ContentStatus::Pending {
+        return Err(format!("Content {} is not finalized yet", content_id).into());
+    }
+    if content.s3_key(bucket) != Some(key) {
+        warn!("Object {} is not the content's current object", key);
+        return Ok(());
+    }
+    // S3 can deliver a notification more than once.
+    if !content.variants.is_empty() {
+        info!("Content {} is already processed", content_id);
+        return Ok(());
+    }
+    let Some(format) = source_format(&content.content_type) else {
+        return Ok(());
+    };
+
+    let object = ctx.s3.get_object().bucket(bucket).key(key).send().await?;
+    let stripped = object
+        .metadata()
+        .is_some_and(|metadata| metadata.contains_key(STRIPPED_METADATA));
+    let data = object.body.collect().await?.into_bytes();
+    let mut size = data.len() as i64;
+
+    let config = ctx.config.images.clone();
+    let renditions =
+        match tokio::task::spawn_blocking(move || render(&data, format, &config)).await? {
+            Ok(renditions) => renditions,
+            Err(err) => {
+                warn!("Could not process image {}: {}", content_id, err);
+                return Ok(());
+            }
+        };
+
+    // The upload itself may carry GPS coordinates and camera details, so it is replaced
+    // by the full-size rendition in its own format.
+    let full_size = renditions
+        .iter()
+        .filter(|rendition| rendition.content_type == format.to_mime_type())
+        .max_by_key(|rendition| rendition.width)
+        .map(|rendition| rendition.bytes.clone());
+
+    let mut variants = Vec::with_capacity(renditions.len());
+    for rendition in renditions {
+        let variant_key = format!("variants/{}/{}", content_id, rendition.name);
+        let size = rendition.bytes.len() as i64;
+        ctx.s3
+            .put_object()
+            .bucket(bucket)
+            .key(&variant_key)
+            .content_type(rendition.content_type)
+            .body(ByteStream::from(rendition.bytes))
+            .send()
+            .await?;
+        variants.push(ContentVariant {
+            key: variant_key,
+            content_type: rendition.content_type.to_string(),
+            width: rendition.width,
+            height: rendition.height,
+            size,
+        });
+    }
+
+    info!("Stored {} variants of {}", variants.len(), content_id);
+
+    if let (false, Some(bytes)) = (stripped, full_size) {
+        size = bytes.len() as i64;
+        ctx.s3
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .content_type(&content.content_type)
+            .metadata(STRIPPED_METADATA, "true")
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        info!("Replaced {} with a copy without metadata", key);
+    }
+
+    if !set_content_variants(&ctx.dynamodb, table_name, &pk, &variants, size).await? {
+        warn!("Content {} was deleted while it was processed", content_id);
+    }
+
+    Ok(())
+}
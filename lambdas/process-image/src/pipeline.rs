@@ -0,0 +1,255 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult};
+use shared::images::ImageConfig;
+use std::io::Cursor;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// One encoded image, ready to be stored.
+pub struct Rendition {
+    pub name: String,
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// The image formats process-image can decode, by content type.
+pub fn source_format(content_type: &str) -> Option<ImageFormat> {
+    match content_type.split(';').next().unwrap_or_default().trim() {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+fn extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "jpg",
+        ImageFormat::WebP => "webp",
+        _ => "png",
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> ImageResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        // JPEG has no alpha channel.
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY))?,
+        ImageFormat::WebP => image
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?,
+        _ => image.write_with_encoder(PngEncoder::new(&mut bytes))?,
+    }
+    Ok(bytes)
+}
+
+/// Decodes an image and produces the renditions `config` asks for.
+///
+/// The EXIF orientation is applied to the pixels before encoding. None of the encoders
+/// write EXIF, so location and camera metadata are dropped from every rendition,
+/// including the full-size one. Lossless WebP of a photo is often larger than the JPEG,
+/// so a WebP rendition is only kept when it is smaller than the one in the original
+/// format at the same width.
+pub fn render(
+    data: &[u8],
+    format: ImageFormat,
+    config: &ImageConfig,
+) -> ImageResult<Vec<Rendition>> {
+    let mut decoder = ImageReader::with_format(Cursor::new(data), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut original = DynamicImage::from_decoder(decoder)?;
+    original.apply_orientation(orientation);
+
+    let mut widths: Vec<u32> = config
+        .widths
+        .iter()
+        .copied()
+        .filter(|&w| w > 0 && w < original.width())
+        .collect();
+    widths.push(original.width());
+    widths.sort_unstable();
+    widths.dedup();
+
+    let webp = config.webp && format != ImageFormat::WebP;
+
+    let mut renditions = Vec::new();
+    for width in widths {
+        let image = if width == original.width() {
+            original.clone()
+        } else {
+            original.resize(width, u32::MAX, FilterType::CatmullRom)
+        };
+        let rendition = |format: ImageFormat| -> ImageResult<Rendition> {
+            Ok(Rendition {
+                name: format!("w{}.{}", image.width(), extension(format)),
+                content_type: format.to_mime_type(),
+                width: image.width(),
+                height: image.height(),
+                bytes: encode(&image, format)?,
+            })
+        };
+
+        let same_format = rendition(format)?;
+        let smaller_webp = if webp {
+            Some(rendition(ImageFormat::WebP)?)
+                .filter(|webp| webp.bytes.len() < same_format.bytes.len())
+        } else {
+            None
+        };
+        renditions.push(same_format);
+        renditions.extend(smaller_webp);
+    }
+    Ok(renditions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegDecoder;
+    use image::{ImageEncoder, RgbImage};
+
+    fn config(widths: &[u32], webp: bool) -> ImageConfig {
+        ImageConfig {
+            widths: widths.to_vec(),
+            webp,
+        }
+    }
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 128]))
+    }
+
+    /// Pixels that no encoder can compress much.
+    fn noise(width: u32, height: u32) -> RgbImage {
+        let mut state: u32 = 12345;
+        RgbImage::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let [r, g, b, _] = state.to_le_bytes();
+            image::Rgb([r, g, b])
+        })
+    }
+
+    fn png(image: &RgbImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image
+            .write_with_encoder(PngEncoder::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    fn jpeg(image: &RgbImage, exif: Option<Vec<u8>>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY);
+        if let Some(exif) = exif {
+            encoder.set_exif_metadata(exif).unwrap();
+        }
+        image.write_with_encoder(encoder).unwrap();
+        bytes
+    }
+
+    /// A little-endian TIFF header with a single Orientation entry.
+    fn exif_orientation(value: u16) -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend(8u32.to_le_bytes());
+        exif.extend(1u16.to_le_bytes());
+        exif.extend(0x0112u16.to_le_bytes());
+        exif.extend(3u16.to_le_bytes());
+        exif.extend(1u32.to_le_bytes());
+        exif.extend(value.to_le_bytes());
+        exif.extend([0, 0]);
+        exif.extend(0u32.to_le_bytes());
+        exif
+    }
+
+    fn sizes(renditions: &[Rendition]) -> Vec<(&str, u32, u32)> {
+        renditions
+            .iter()
+            .map(|r| (r.name.as_str(), r.width, r.height))
+            .collect()
+    }
+
+    #[test]
+    fn renders_each_smaller_width_and_the_full_size() {
+        let data = png(&gradient(100, 40));
+        let renditions = render(&data, ImageFormat::Png, &config(&[50, 20, 0], false)).unwrap();
+        assert_eq!(
+            sizes(&renditions),
+            vec![
+                ("w20.png", 20, 8),
+                ("w50.png", 50, 20),
+                ("w100.png", 100, 40)
+            ]
+        );
+        assert!(renditions.iter().all(|r| r.content_type == "image/png"));
+    }
+
+    #[test]
+    fn never_upscales() {
+        let data = png(&gradient(100, 40));
+        let renditions = render(&data, ImageFormat::Png, &config(&[100, 200], false)).unwrap();
+        assert_eq!(sizes(&renditions), vec![("w100.png", 100, 40)]);
+    }
+
+    #[test]
+    fn applies_the_orientation_and_drops_exif() {
+        // Orientation 6: the stored pixels are rotated 90 degrees counterclockwise.
+        let data = jpeg(&gradient(40, 20), Some(exif_orientation(6)));
+        let mut decoder = JpegDecoder::new(Cursor::new(&data)).unwrap();
+        assert!(decoder.exif_metadata().unwrap().is_some());
+
+        let renditions = render(&data, ImageFormat::Jpeg, &config(&[], false)).unwrap();
+        assert_eq!(sizes(&renditions), vec![("w20.jpg", 20, 40)]);
+
+        let mut decoder = JpegDecoder::new(Cursor::new(&renditions[0].bytes)).unwrap();
+        assert_eq!(decoder.exif_metadata().unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_webp_only_when_it_is_smaller() {
+        let flat = jpeg(
+            &RgbImage::from_pixel(64, 64, image::Rgb([200, 30, 30])),
+            None,
+        );
+        let renditions = render(&flat, ImageFormat::Jpeg, &config(&[32], true)).unwrap();
+        assert_eq!(
+            sizes(&renditions),
+            vec![
+                ("w32.jpg", 32, 32),
+                ("w32.webp", 32, 32),
+                ("w64.jpg", 64, 64),
+                ("w64.webp", 64, 64)
+            ]
+        );
+        for pair in renditions.chunks(2) {
+            assert!(pair[1].bytes.len() < pair[0].bytes.len());
+        }
+
+        let noisy = jpeg(&noise(64, 64), None);
+        let renditions = render(&noisy, ImageFormat::Jpeg, &config(&[32], true)).unwrap();
+        assert_eq!(
+            sizes(&renditions),
+            vec![("w32.jpg", 32, 32), ("w64.jpg", 64, 64)]
+        );
+    }
+
+    #[test]
+    fn webp_originals_get_no_second_webp() {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(gradient(40, 20))
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut data))
+            .unwrap();
+        let renditions = render(&data, ImageFormat::WebP, &config(&[20], true)).unwrap();
+        assert_eq!(
+            sizes(&renditions),
+            vec![("w20.webp", 20, 10), ("w40.webp", 40, 20)]
+        );
+    }
+}
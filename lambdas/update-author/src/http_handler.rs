@@ -0,0 +1,126 @@
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::HeaderMap;
+use chrono::Utc;
+use lambda_runtime::LambdaEvent;
+use shared::api::require_any_scope;
+use shared::context::AppContext;
+use shared::db::{get_content, put_author_profile};
+use shared::errors::error_response;
+use shared::http::ApiRequest;
+use shared::models::{AuthorProfile, ContentStatus, UpdateAuthorProfileRequest};
+use tracing::info;
+
+const MAX_DISPLAY_NAME_CHARS: usize = 100;
+const MAX_BIO_CHARS: usize = 2000;
+const MAX_SOCIAL_LINKS: usize = 10;
+
+fn validate(request: &UpdateAuthorProfileRequest) -> Result<(), String> {
+    let display_name = request.display_name.trim();
+    if display_name.is_empty() || display_name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+        return Err(format!(
+            "displayName must be 1 to {} characters",
+            MAX_DISPLAY_NAME_CHARS
+        ));
+    }
+    if request.bio.chars().count() > MAX_BIO_CHARS {
+        return Err(format!("bio must be at most {} characters", MAX_BIO_CHARS));
+    }
+    if request.social_links.len() > MAX_SOCIAL_LINKS {
+        return Err(format!(
+            "at most {} socialLinks are allowed",
+            MAX_SOCIAL_LINKS
+        ));
+    }
+    if let Some((name, _)) = request
+        .social_links
+        .iter()
+        .find(|(_, url)| !url.starts_with("https://"))
+    {
+        return Err(format!("socialLinks.{} must be an https URL", name));
+    }
+    Ok(())
+}
+
+/// Creates or replaces the caller's own author profile.
+pub async fn function_handler(
+    ctx: &AppContext,
+    event: LambdaEvent<ApiRequest>,
+) -> Result<ApiGatewayProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let request = event.payload;
+
+    let user = match require_any_scope(&request, &["author.write", "admin.write"]) {
+        Ok(user) => user,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    let Some(body) = request.body else {
+        return Ok(error_response(400, "Missing body"));
+    };
+    let update: UpdateAuthorProfileRequest = match serde_json::from_str(&body) {
+        Ok(update) => update,
+        Err(e) => return Ok(error_response(400, &format!("Invalid profile: {}", e))),
+    };
+    if let Err(e) = validate(&update) {
+        return Ok(error_response(400, &e));
+    }
+
+    // The avatar is an image the caller uploaded beforehand, checked like a post's
+    // contentKey.
+    let avatar_content_id = match update.avatar_content_id.as_deref().map(str::trim) {
+        Some(id) => {
+            let id = id.strip_prefix("CONTENT#").unwrap_or(id);
+            if id.is_empty() {
+                return Ok(error_response(400, "avatarContentId must not be empty"));
+            }
+            let content_table = ctx.config.content_table()?;
+            let Some(content) =
+                get_content(&ctx.dynamodb, content_table, &format!("CONTENT#{}", id)).await?
+            else {
+                return Ok(error_response(
+                    422,
+                    "avatarContentId does not match any content",
+                ));
+            };
+            if content.author_id != user.sub && !user.has_scope("admin.write") {
+                return Ok(error_response(403, "Forbidden"));
+            }
+            if !content.is_image() {
+                return Ok(error_response(415, "avatarContentId must be an image"));
+            }
+            if content.status == ContentStatus::Pending {
+                return Ok(error_response(409, "Content upload has not been finalized"));
+            }
+            Some(id.to_string())
+        }
+        None => None,
+    };
+
+    let table_name = ctx.config.posts_table()?;
+
+    let profile = AuthorProfile {
+        pk: format!("AUTHOR#{}", user.sub),
+        sk: "META".to_string(),
+        author_id: user.sub,
+        display_name: update.display_name.trim().to_string(),
+        bio: update.bio,
+        avatar_content_id,
+        social_links: update.social_links,
+        updated_at: Utc::now().to_rfc3339(),
+    };
+
+    info!("Author profile: {:?}", profile);
+
+    put_author_profile(&ctx.dynamodb, table_name, &profile).await?;
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    Ok(ApiGatewayProxyResponse {
+        status_code: 200,
+        headers: header_map,
+        body: Some(Body::Text(serde_json::to_string(&profile)?)),
+        ..Default::default()
+    })
+}
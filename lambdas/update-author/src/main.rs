@@ -0,0 +1,17 @@
+use lambda_runtime::{run, service_fn, tracing, Error};
+use shared::config::Setting;
+use shared::context::AppContext;
+use shared::http::handle_http_event;
+use update_author::http_handler::function_handler;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let ctx = AppContext::load(&[Setting::PostsTable, Setting::ContentTable]).await?;
+
+    run(service_fn(|event| {
+        handle_http_event(&ctx, event, |event| function_handler(&ctx, event))
+    }))
+    .await
+}
@@ -1,19 +1,128 @@
-use crate::models::Claims;
-use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
-use serde_json::from_value;
-use std::error::Error;
-
-pub fn get_author_id_from_request_context(
-    request_context: ApiGatewayProxyRequestContext,
-) -> Result<String, Box<dyn Error + Send + Sync>> {
-    let fields = request_context.authorizer.fields;
-
-    let claims: Claims = match fields.get("claims") {
-        Some(value) => from_value::<Claims>(value.clone()).unwrap_or_default(),
-        None => Claims::default(),
-    };
-
-    let author_id = claims.sub;
-
-    Ok(author_id.to_string())
-}
+use crate::errors::error_response;
+use crate::http::ApiRequest;
+use crate::jwt::JwtVerifier;
+use crate::models::Claims;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use aws_lambda_events::http::HeaderValue;
+use serde_json::{from_value, Value};
+use std::collections::HashSet;
+use tracing::info;
+
+/// Header an ALB authenticate-cognito action puts the user's access token in.
+const ALB_ACCESS_TOKEN: &str = "x-amzn-oidc-accesstoken";
+
+/// The caller behind a request, as asserted by the authorizer's token claims.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub sub: String,
+    pub username: String,
+    /// Granted scopes without their resource server prefix, e.g. `author.write`.
+    pub scopes: HashSet<String>,
+    pub claims: Claims,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No usable claims on the request.
+    Unauthenticated,
+    /// Authenticated, but none of the required scopes were granted.
+    Forbidden,
+}
+
+impl AuthError {
+    pub fn into_response(self) -> ApiGatewayProxyResponse {
+        match self {
+            AuthError::Unauthenticated => {
+                let mut response = error_response(401, "Unauthorized");
+                response
+                    .headers
+                    .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                response
+            }
+            AuthError::Forbidden => error_response(403, "Forbidden"),
+        }
+    }
+}
+
+impl AuthenticatedUser {
+    pub fn from_request(request: &ApiRequest) -> Result<Self, AuthError> {
+        let claims: Claims = request
+            .claims
+            .as_ref()
+            .and_then(|value| from_value(Value::Object(value.clone())).ok())
+            .ok_or(AuthError::Unauthenticated)?;
+
+        if claims.sub.is_empty() || claims.is_expired() {
+            return Err(AuthError::Unauthenticated);
+        }
+
+        let scopes = claims.scope.names().map(str::to_string).collect();
+
+        Ok(AuthenticatedUser {
+            sub: claims.sub.clone(),
+            username: claims.username.clone(),
+            scopes,
+            claims,
+        })
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.contains(scope)
+    }
+
+    pub fn require_any_scope(&self, scopes: &[&str]) -> Result<(), AuthError> {
+        if scopes.iter().any(|scope| self.has_scope(scope)) {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+}
+
+/// Fills in `request.claims` from an `Authorization: Bearer` token, or the Cognito
+/// access token an ALB authenticate action forwards in `x-amzn-oidc-accesstoken`, when
+/// no authorizer has already done so. Requests without a token are left anonymous.
+pub async fn authenticate_bearer(
+    verifier: &JwtVerifier,
+    request: &mut ApiRequest,
+) -> Result<(), AuthError> {
+    if request.claims.is_some() {
+        return Ok(());
+    }
+
+    let token = if let Some(header) = request.headers.get(AUTHORIZATION) {
+        header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::Unauthenticated)?
+    } else if let Some(header) = request.headers.get(ALB_ACCESS_TOKEN) {
+        header.to_str().map_err(|_| AuthError::Unauthenticated)?
+    } else {
+        return Ok(());
+    };
+
+    match verifier.verify_claims(token.trim()).await {
+        Ok(claims) => {
+            request.claims = Some(claims);
+            Ok(())
+        }
+        Err(e) => {
+            info!("Rejected bearer token: {}", e);
+            Err(AuthError::Unauthenticated)
+        }
+    }
+}
+
+/// Authenticates the caller and checks that they hold at least one of `scopes`.
+///
+/// On failure, handlers should return `AuthError::into_response` as-is.
+pub fn require_any_scope(
+    request: &ApiRequest,
+    scopes: &[&str],
+) -> Result<AuthenticatedUser, AuthError> {
+    let user = AuthenticatedUser::from_request(request)?;
+    user.require_any_scope(scopes)?;
+    Ok(user)
+}
@@ -0,0 +1,466 @@
+use crate::cors::CorsConfig;
+use crate::delivery::DeliveryConfig;
+use crate::images::ImageConfig;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::path::Path;
+
+/// Environment variable pointing at an optional TOML or JSON settings file.
+///
+/// Values from the file are used as defaults; environment variables always win.
+pub const CONFIG_FILE_VAR: &str = "BLOG_CONFIG_FILE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    PostsTable,
+    ContentTable,
+    ContentBucket,
+    UsersTable,
+    Stage,
+    ApiBlogDomain,
+    CognitoUserPoolId,
+    CognitoClientId,
+    CognitoUserPasswordSecretName,
+    CognitoJwksFile,
+    GuestSigningKeySecretName,
+    GuestSigningKeyFile,
+    GuestTokenIssuer,
+    GuestTokenJwksUrl,
+    ScopeMap,
+    ScopeMapParameter,
+    ScopeMapFile,
+}
+
+impl Setting {
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            Setting::PostsTable => "BLOG_POSTS_TABLE",
+            Setting::ContentTable => "BLOG_CONTENT_TABLE",
+            Setting::ContentBucket => "BLOG_CONTENT_BUCKET",
+            Setting::UsersTable => "BLOG_USERS_TABLE",
+            Setting::Stage => "STAGE",
+            Setting::ApiBlogDomain => "API_BLOG_DOMAIN",
+            Setting::CognitoUserPoolId => "COGNITO_USER_POOL_ID",
+            Setting::CognitoClientId => "COGNITO_CLIENT_ID",
+            Setting::CognitoUserPasswordSecretName => "COGNITO_USER_PASSWORD_SECRET_NAME",
+            Setting::CognitoJwksFile => "COGNITO_JWKS_FILE",
+            Setting::GuestSigningKeySecretName => "GUEST_SIGNING_KEY_SECRET_NAME",
+            Setting::GuestSigningKeyFile => "GUEST_SIGNING_KEY_FILE",
+            Setting::GuestTokenIssuer => "GUEST_TOKEN_ISSUER",
+            Setting::GuestTokenJwksUrl => "GUEST_TOKEN_JWKS_URL",
+            Setting::ScopeMap => "SCOPE_MAP",
+            Setting::ScopeMapParameter => "SCOPE_MAP_PARAMETER",
+            Setting::ScopeMapFile => "SCOPE_MAP_FILE",
+        }
+    }
+}
+
+fn default_max_page_size() -> i32 {
+    50
+}
+
+fn default_max_upload_bytes() -> usize {
+    // API Gateway rejects payloads above 10 MB; base64 inflates the body by a third.
+    7 * 1024 * 1024
+}
+
+fn default_max_presigned_upload_bytes() -> i64 {
+    100 * 1024 * 1024
+}
+
+fn default_upload_url_ttl() -> u64 {
+    900
+}
+
+fn default_guest_token_ttl() -> i64 {
+    900
+}
+
+fn default_guest_tokens_per_minute() -> u32 {
+    10
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Limits {
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: i32,
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: usize,
+    /// Largest file accepted through a presigned upload URL.
+    #[serde(default = "default_max_presigned_upload_bytes")]
+    pub max_presigned_upload_bytes: i64,
+    /// Seconds a presigned upload URL stays valid.
+    #[serde(default = "default_upload_url_ttl")]
+    pub upload_url_ttl: u64,
+    /// Lifetime in seconds of self-signed guest tokens.
+    #[serde(default = "default_guest_token_ttl")]
+    pub guest_token_ttl: i64,
+    /// Self-signed guest tokens issued per source IP per minute, per lambda instance.
+    #[serde(default = "default_guest_tokens_per_minute")]
+    pub guest_tokens_per_minute: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_page_size: default_max_page_size(),
+            max_upload_bytes: default_max_upload_bytes(),
+            max_presigned_upload_bytes: default_max_presigned_upload_bytes(),
+            upload_url_ttl: default_upload_url_ttl(),
+            guest_token_ttl: default_guest_token_ttl(),
+            guest_tokens_per_minute: default_guest_tokens_per_minute(),
+        }
+    }
+}
+
+fn default_gc_grace_period() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+/// What content-gc removes. It only reports unless `delete` is set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GcConfig {
+    #[serde(default)]
+    pub delete: bool,
+    /// Seconds content and objects are left alone after they are created, so uploads
+    /// in progress and posts still being written are not collected.
+    #[serde(default = "default_gc_grace_period")]
+    pub grace_period: u64,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            delete: false,
+            grace_period: default_gc_grace_period(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    pub posts_table: Option<String>,
+    pub content_table: Option<String>,
+    pub content_bucket: Option<String>,
+    pub users_table: Option<String>,
+    pub stage: Option<String>,
+    pub api_blog_domain: Option<String>,
+    pub cognito_user_pool_id: Option<String>,
+    pub cognito_client_id: Option<String>,
+    pub cognito_user_password_secret_name: Option<String>,
+    /// Local JWKS used instead of the user pool's published keys, for tests.
+    pub cognito_jwks_file: Option<String>,
+    /// PEM private key used to sign guest tokens locally instead of calling Cognito.
+    pub guest_signing_key_secret_name: Option<String>,
+    pub guest_signing_key_file: Option<String>,
+    pub guest_token_issuer: Option<String>,
+    /// Where guest-jwt-generator publishes its public key, when it is not under the issuer.
+    pub guest_token_jwks_url: Option<String>,
+    /// Group-to-scope mapping for pretoken-generation, given inline, as a parameter
+    /// name or as a file path.
+    pub scope_map: Option<String>,
+    pub scope_map_parameter: Option<String>,
+    pub scope_map_file: Option<String>,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+    #[serde(default)]
+    pub images: ImageConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+}
+
+#[derive(Debug, Default)]
+pub struct ConfigError {
+    pub missing: Vec<&'static str>,
+    pub invalid: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut problems = Vec::new();
+        if !self.missing.is_empty() {
+            problems.push(format!(
+                "missing required settings: {}",
+                self.missing.join(", ")
+            ));
+        }
+        if !self.invalid.is_empty() {
+            problems.push(format!("invalid settings: {}", self.invalid.join("; ")));
+        }
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads settings from the optional config file and the environment, failing with
+    /// every missing or malformed value at once if any of `required` is absent.
+    pub fn load(required: &[Setting]) -> Result<Self, ConfigError> {
+        let mut errors = ConfigError::default();
+
+        let mut config = match env::var(CONFIG_FILE_VAR) {
+            Ok(path) => Self::from_file(Path::new(&path)).unwrap_or_else(|e| {
+                errors.invalid.push(e);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        };
+
+        config.apply_env(&mut errors);
+        config.cors.resolve_stage(config.stage.as_deref());
+        config.collect_missing(required, &mut errors);
+
+        if errors.missing.is_empty() && errors.invalid.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks settings whose necessity is only known after loading, e.g. because it
+    /// depends on which mode another setting selects.
+    pub fn check(&self, required: &[Setting]) -> Result<(), ConfigError> {
+        let mut errors = ConfigError::default();
+        self.collect_missing(required, &mut errors);
+        if errors.missing.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn collect_missing(&self, required: &[Setting], errors: &mut ConfigError) {
+        for setting in required {
+            if self.get(*setting).is_none() {
+                errors.missing.push(setting.env_var());
+            }
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => {
+                serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Some("toml") => {
+                toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            _ => Err(format!(
+                "{}: expected a .toml or .json file",
+                path.display()
+            )),
+        }
+    }
+
+    fn apply_env(&mut self, errors: &mut ConfigError) {
+        for setting in [
+            Setting::PostsTable,
+            Setting::ContentTable,
+            Setting::ContentBucket,
+            Setting::UsersTable,
+            Setting::Stage,
+            Setting::ApiBlogDomain,
+            Setting::CognitoUserPoolId,
+            Setting::CognitoClientId,
+            Setting::CognitoUserPasswordSecretName,
+            Setting::CognitoJwksFile,
+            Setting::GuestSigningKeySecretName,
+            Setting::GuestSigningKeyFile,
+            Setting::GuestTokenIssuer,
+            Setting::ScopeMap,
+            Setting::ScopeMapParameter,
+            Setting::ScopeMapFile,
+        ] {
+            if let Some(value) = env::var(setting.env_var()).ok().filter(|v| !v.is_empty()) {
+                *self.slot(setting) = Some(value);
+            }
+        }
+
+        if let Some(value) = parse_env("BLOG_MAX_PAGE_SIZE", errors) {
+            self.limits.max_page_size = value;
+        }
+        if let Some(value) = parse_env("BLOG_MAX_UPLOAD_BYTES", errors) {
+            self.limits.max_upload_bytes = value;
+        }
+        if let Some(value) = parse_env("BLOG_MAX_PRESIGNED_UPLOAD_BYTES", errors) {
+            self.limits.max_presigned_upload_bytes = value;
+        }
+        if let Some(value) = parse_env("BLOG_UPLOAD_URL_TTL", errors) {
+            self.limits.upload_url_ttl = value;
+        }
+        if let Some(value) = parse_env("BLOG_GUEST_TOKEN_TTL", errors) {
+            self.limits.guest_token_ttl = value;
+        }
+        if let Some(value) = parse_env("BLOG_GUEST_TOKENS_PER_MINUTE", errors) {
+            self.limits.guest_tokens_per_minute = value;
+        }
+
+        if let Some(value) = list_env("CORS_ALLOWED_ORIGINS") {
+            self.cors.allowed_origins = value;
+            // The file's per-stage lists must not win over an explicit setting.
+            self.cors.stage_origins.clear();
+        }
+        if let Some(value) = list_env("CORS_ALLOWED_METHODS") {
+            self.cors.allowed_methods = value;
+        }
+        if let Some(value) = list_env("CORS_ALLOWED_HEADERS") {
+            self.cors.allowed_headers = value;
+        }
+        if let Some(value) = list_env("CORS_EXPOSE_HEADERS") {
+            self.cors.expose_headers = value;
+        }
+        if let Some(value) = parse_env("CORS_ALLOW_CREDENTIALS", errors) {
+            self.cors.allow_credentials = value;
+        }
+        if let Some(value) = parse_env("CORS_MAX_AGE", errors) {
+            self.cors.max_age = value;
+        }
+
+        if let Some(value) = parse_env("CONTENT_REDIRECT", errors) {
+            self.delivery.redirect = value;
+        }
+        if let Some(value) = parse_env("CONTENT_INLINE_MAX_BYTES", errors) {
+            self.delivery.inline_max_bytes = value;
+        }
+        if let Some(value) = list_env("CONTENT_INLINE_TYPES") {
+            self.delivery.inline_content_types = value;
+        }
+        if let Some(value) = parse_env("CONTENT_URL_TTL", errors) {
+            self.delivery.url_ttl = value;
+        }
+        if let Some(value) = env::var("CONTENT_PUBLIC_URL")
+            .ok()
+            .filter(|v| !v.is_empty())
+        {
+            self.delivery.public_url = Some(value);
+        }
+
+        if let Some(value) = parse_env("GC_DELETE", errors) {
+            self.gc.delete = value;
+        }
+        if let Some(value) = parse_env("GC_GRACE_PERIOD", errors) {
+            self.gc.grace_period = value;
+        }
+
+        if let Some(value) = list_env("IMAGE_WIDTHS") {
+            match value.iter().map(|w| w.parse()).collect() {
+                Ok(widths) => self.images.widths = widths,
+                Err(_) => errors
+                    .invalid
+                    .push(format!("IMAGE_WIDTHS has an invalid value: {:?}", value)),
+            }
+        }
+        if let Some(value) = parse_env("IMAGE_WEBP", errors) {
+            self.images.webp = value;
+        }
+    }
+
+    fn slot(&mut self, setting: Setting) -> &mut Option<String> {
+        match setting {
+            Setting::PostsTable => &mut self.posts_table,
+            Setting::ContentTable => &mut self.content_table,
+            Setting::ContentBucket => &mut self.content_bucket,
+            Setting::UsersTable => &mut self.users_table,
+            Setting::Stage => &mut self.stage,
+            Setting::ApiBlogDomain => &mut self.api_blog_domain,
+            Setting::CognitoUserPoolId => &mut self.cognito_user_pool_id,
+            Setting::CognitoClientId => &mut self.cognito_client_id,
+            Setting::CognitoUserPasswordSecretName => &mut self.cognito_user_password_secret_name,
+            Setting::CognitoJwksFile => &mut self.cognito_jwks_file,
+            Setting::GuestSigningKeySecretName => &mut self.guest_signing_key_secret_name,
+            Setting::GuestSigningKeyFile => &mut self.guest_signing_key_file,
+            Setting::GuestTokenIssuer => &mut self.guest_token_issuer,
+            Setting::GuestTokenJwksUrl => &mut self.guest_token_jwks_url,
+            Setting::ScopeMap => &mut self.scope_map,
+            Setting::ScopeMapParameter => &mut self.scope_map_parameter,
+            Setting::ScopeMapFile => &mut self.scope_map_file,
+        }
+    }
+
+    pub fn get(&self, setting: Setting) -> Option<&str> {
+        let value = match setting {
+            Setting::PostsTable => &self.posts_table,
+            Setting::ContentTable => &self.content_table,
+            Setting::ContentBucket => &self.content_bucket,
+            Setting::UsersTable => &self.users_table,
+            Setting::Stage => &self.stage,
+            Setting::ApiBlogDomain => &self.api_blog_domain,
+            Setting::CognitoUserPoolId => &self.cognito_user_pool_id,
+            Setting::CognitoClientId => &self.cognito_client_id,
+            Setting::CognitoUserPasswordSecretName => &self.cognito_user_password_secret_name,
+            Setting::CognitoJwksFile => &self.cognito_jwks_file,
+            Setting::GuestSigningKeySecretName => &self.guest_signing_key_secret_name,
+            Setting::GuestSigningKeyFile => &self.guest_signing_key_file,
+            Setting::GuestTokenIssuer => &self.guest_token_issuer,
+            Setting::GuestTokenJwksUrl => &self.guest_token_jwks_url,
+            Setting::ScopeMap => &self.scope_map,
+            Setting::ScopeMapParameter => &self.scope_map_parameter,
+            Setting::ScopeMapFile => &self.scope_map_file,
+        };
+        value.as_deref()
+    }
+
+    /// Returns a setting that was declared as required when loading.
+    pub fn require(&self, setting: Setting) -> Result<&str, String> {
+        self.get(setting)
+            .ok_or_else(|| format!("{} not set", setting.env_var()))
+    }
+
+    pub fn posts_table(&self) -> Result<&str, String> {
+        self.require(Setting::PostsTable)
+    }
+
+    pub fn content_table(&self) -> Result<&str, String> {
+        self.require(Setting::ContentTable)
+    }
+
+    pub fn content_bucket(&self) -> Result<&str, String> {
+        self.require(Setting::ContentBucket)
+    }
+
+    pub fn users_table(&self) -> Result<&str, String> {
+        self.require(Setting::UsersTable)
+    }
+
+    pub fn stage(&self) -> Result<&str, String> {
+        self.require(Setting::Stage)
+    }
+
+    pub fn api_blog_domain(&self) -> Result<&str, String> {
+        self.require(Setting::ApiBlogDomain)
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, errors: &mut ConfigError) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            errors
+                .invalid
+                .push(format!("{} has an invalid value: {:?}", name, value));
+            None
+        }
+    }
+}
+
+fn list_env(name: &str) -> Option<Vec<String>> {
+    let value = env::var(name).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
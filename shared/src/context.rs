@@ -0,0 +1,112 @@
+use crate::config::{Config, ConfigError, Setting};
+use crate::jwt::{JwksSource, JwtVerifier};
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use std::sync::Arc;
+
+/// AWS clients and settings shared by every invocation of a lambda.
+///
+/// Build it once in `main` and hand a reference to the handler, so the SDK config
+/// is only resolved on cold start.
+#[derive(Debug, Clone)]
+pub struct AppContext {
+    pub sdk_config: SdkConfig,
+    pub config: Config,
+    pub dynamodb: aws_sdk_dynamodb::Client,
+    pub s3: aws_sdk_s3::Client,
+    pub secrets_manager: aws_sdk_secretsmanager::Client,
+    pub cognito: aws_sdk_cognitoidentityprovider::Client,
+    /// Present when `COGNITO_USER_POOL_ID` or `GUEST_TOKEN_ISSUER` is set, for requests
+    /// that reach us without an API Gateway authorizer.
+    pub jwt_verifier: Option<Arc<JwtVerifier>>,
+}
+
+impl AppContext {
+    /// Validates the settings the lambda needs before resolving the SDK config, so a
+    /// misconfigured function fails on cold start rather than on its first request.
+    pub async fn load(required: &[Setting]) -> Result<Self, ConfigError> {
+        let config = Config::load(required)?;
+        let sdk_config = aws_config::load_from_env().await;
+        Ok(Self::new(sdk_config, config))
+    }
+
+    pub fn new(sdk_config: SdkConfig, config: Config) -> Self {
+        // Path-style addressing keeps S3 working against local endpoints such as LocalStack.
+        let s3_config = aws_sdk_s3::config::Builder::from(&sdk_config)
+            .force_path_style(sdk_config.endpoint_url().is_some())
+            .build();
+
+        let region = sdk_config
+            .region()
+            .map(|r| r.to_string())
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let mut jwt_verifier = config.cognito_user_pool_id.as_deref().map(|user_pool_id| {
+            let client_ids = config
+                .cognito_client_id
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect();
+
+            let mut verifier = JwtVerifier::for_user_pool(&region, user_pool_id, client_ids);
+            if let Some(path) = &config.cognito_jwks_file {
+                verifier = verifier.with_jwks_source(JwksSource::File(path.into()));
+            }
+            verifier
+        });
+        if let Some(issuer) = config.guest_token_issuer.as_deref() {
+            let jwks_url = config.guest_token_jwks_url.clone().unwrap_or_else(|| {
+                format!("{}/.well-known/jwks.json", issuer.trim_end_matches('/'))
+            });
+            let source = JwksSource::Url(jwks_url);
+            jwt_verifier = Some(match jwt_verifier {
+                Some(verifier) => verifier.with_guest_issuer(issuer, source),
+                None => JwtVerifier::for_guest_issuer(issuer, source),
+            });
+        }
+        let jwt_verifier = jwt_verifier.map(Arc::new);
+
+        AppContext {
+            jwt_verifier,
+            dynamodb: aws_sdk_dynamodb::Client::new(&sdk_config),
+            s3: aws_sdk_s3::Client::from_conf(s3_config),
+            secrets_manager: aws_sdk_secretsmanager::Client::new(&sdk_config),
+            cognito: aws_sdk_cognitoidentityprovider::Client::new(&sdk_config),
+            sdk_config,
+            config,
+        }
+    }
+
+    /// Builds a context whose clients all talk to `endpoint_url` with static test
+    /// credentials, for running handlers against LocalStack or DynamoDB Local.
+    pub async fn for_endpoint(endpoint_url: &str, config: Config) -> Self {
+        let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .endpoint_url(endpoint_url)
+            .test_credentials()
+            .load()
+            .await;
+        Self::new(sdk_config, config)
+    }
+
+    pub fn with_dynamodb(mut self, dynamodb: aws_sdk_dynamodb::Client) -> Self {
+        self.dynamodb = dynamodb;
+        self
+    }
+
+    pub fn with_s3(mut self, s3: aws_sdk_s3::Client) -> Self {
+        self.s3 = s3;
+        self
+    }
+
+    pub fn with_secrets_manager(mut self, secrets_manager: aws_sdk_secretsmanager::Client) -> Self {
+        self.secrets_manager = secrets_manager;
+        self
+    }
+
+    pub fn with_cognito(mut self, cognito: aws_sdk_cognitoidentityprovider::Client) -> Self {
+        self.cognito = cognito;
+        self
+    }
+}
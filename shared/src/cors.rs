@@ -0,0 +1,255 @@
+use crate::http::ApiRequest;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use aws_lambda_events::http::{HeaderMap, HeaderValue, Method};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+fn default_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "DELETE", "OPTIONS"]
+        .iter()
+        .map(|m| m.to_string())
+        .collect()
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    [
+        "Authorization",
+        "Content-Type",
+        "Range",
+        "If-None-Match",
+        "If-Modified-Since",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect()
+}
+
+fn default_expose_headers() -> Vec<String> {
+    ["ETag", "Last-Modified", "Content-Range", "Retry-After"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+}
+
+fn default_max_age() -> u64 {
+    600
+}
+
+/// Cross-origin settings applied to every HTTP response.
+///
+/// With no allowed origins configured, no `Access-Control-*` headers are emitted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API; `*` allows any origin.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Replaces `allowed_origins` for the matching `STAGE`.
+    #[serde(default)]
+    pub stage_origins: HashMap<String, Vec<String>>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts on allowed origins may read.
+    #[serde(default = "default_expose_headers")]
+    pub expose_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            stage_origins: HashMap::new(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            expose_headers: default_expose_headers(),
+            allow_credentials: false,
+            max_age: default_max_age(),
+        }
+    }
+}
+
+impl CorsConfig {
+    pub(crate) fn resolve_stage(&mut self, stage: Option<&str>) {
+        if let Some(origins) = stage.and_then(|stage| self.stage_origins.get(stage)) {
+            self.allowed_origins = origins.clone();
+        }
+    }
+
+    /// Returns the value to send as `Access-Control-Allow-Origin`, if `origin` is allowed.
+    pub fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.allowed_origins.iter().any(|o| o == origin) {
+            return Some(origin.to_string());
+        }
+        if self.allowed_origins.iter().any(|o| o == "*") {
+            // Browsers reject a wildcard on credentialed requests, so echo the origin instead.
+            return Some(if self.allow_credentials {
+                origin.to_string()
+            } else {
+                "*".to_string()
+            });
+        }
+        None
+    }
+
+    /// Adds the CORS headers for a request from `origin` to a response.
+    pub fn apply(&self, origin: Option<&str>, headers: &mut HeaderMap) {
+        if self.allowed_origins.is_empty() {
+            return;
+        }
+
+        add_vary_origin(headers);
+
+        let Some(allowed) = origin.and_then(|origin| self.allow_origin(origin)) else {
+            return;
+        };
+        if let Ok(value) = allowed.parse() {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, value);
+        }
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !self.expose_headers.is_empty() {
+            if let Ok(value) = self.expose_headers.join(", ").parse() {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, value);
+            }
+        }
+    }
+
+    /// Answers a CORS preflight request, or returns `None` if `request` is not one.
+    pub fn preflight_response(&self, request: &ApiRequest) -> Option<ApiGatewayProxyResponse> {
+        if request.http_method != Method::OPTIONS
+            || !request.headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return None;
+        }
+
+        let origin = request.headers.get(ORIGIN).and_then(|v| v.to_str().ok());
+
+        let mut headers = HeaderMap::new();
+        self.apply(origin, &mut headers);
+
+        if headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN) {
+            if let Ok(value) = self.allowed_methods.join(", ").parse() {
+                headers.insert(ACCESS_CONTROL_ALLOW_METHODS, value);
+            }
+            if let Ok(value) = self.allowed_headers.join(", ").parse() {
+                headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, value);
+            }
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(self.max_age));
+        }
+
+        Some(ApiGatewayProxyResponse {
+            status_code: 204,
+            headers,
+            ..Default::default()
+        })
+    }
+}
+
+/// Adds `Origin` to the response's `Vary` header. The integrations only pass on the first
+/// value of a header, so existing values are merged into one rather than appended to.
+fn add_vary_origin(headers: &mut HeaderMap) {
+    let mut fields: Vec<String> = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect();
+    if fields
+        .iter()
+        .any(|field| field == "*" || field.eq_ignore_ascii_case("Origin"))
+    {
+        return;
+    }
+    fields.push("Origin".to_string());
+    if let Ok(value) = fields.join(", ").parse() {
+        headers.insert(VARY, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec!["https://blog.example.com".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn adds_vary_origin() {
+        let mut headers = HeaderMap::new();
+        config().apply(Some("https://blog.example.com"), &mut headers);
+        assert_eq!(headers.get_all(VARY).iter().count(), 1);
+        assert_eq!(headers[VARY], "Origin");
+    }
+
+    #[test]
+    fn merges_origin_into_existing_vary() {
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("Accept"));
+        config().apply(Some("https://blog.example.com"), &mut headers);
+        assert_eq!(headers.get_all(VARY).iter().count(), 1);
+        assert_eq!(headers[VARY], "Accept, Origin");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://blog.example.com"
+        );
+    }
+
+    #[test]
+    fn does_not_repeat_origin_in_vary() {
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("Accept, origin"));
+        config().apply(None, &mut headers);
+        assert_eq!(headers[VARY], "Accept, origin");
+    }
+
+    #[test]
+    fn exposes_headers_to_allowed_origins() {
+        let mut headers = HeaderMap::new();
+        config().apply(Some("https://blog.example.com"), &mut headers);
+        assert_eq!(
+            headers[ACCESS_CONTROL_EXPOSE_HEADERS],
+            "ETag, Last-Modified, Content-Range, Retry-After"
+        );
+
+        let mut headers = HeaderMap::new();
+        config().apply(Some("https://other.example.com"), &mut headers);
+        assert!(!headers.contains_key(ACCESS_CONTROL_EXPOSE_HEADERS));
+
+        let mut headers = HeaderMap::new();
+        CorsConfig {
+            expose_headers: vec!["X-Request-Id".to_string()],
+            ..config()
+        }
+        .apply(Some("https://blog.example.com"), &mut headers);
+        assert_eq!(headers[ACCESS_CONTROL_EXPOSE_HEADERS], "X-Request-Id");
+
+        let mut headers = HeaderMap::new();
+        CorsConfig {
+            expose_headers: Vec::new(),
+            ..config()
+        }
+        .apply(Some("https://blog.example.com"), &mut headers);
+        assert!(!headers.contains_key(ACCESS_CONTROL_EXPOSE_HEADERS));
+    }
+}
@@ -1,12 +1,22 @@
-use crate::models::{BlogPost, Comment, Content};
+use crate::models::{
+    AuthorProfile, AuthorSummary, BlogPost, Comment, Content, ContentHash, ContentStatus,
+    ContentVariant, User,
+};
 use aws_sdk_dynamodb::error::SdkError;
 use aws_sdk_dynamodb::operation::get_item::GetItemOutput;
-use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
 use aws_sdk_dynamodb::Client;
 use serde_dynamo::{from_item, from_items, to_item};
 use std::collections::HashMap;
+use std::time::Duration;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
+
+/// BatchGetItem accepts at most this many keys per request.
+const BATCH_GET_LIMIT: usize = 100;
+/// Rounds of retrying keys DynamoDB returns as unprocessed before giving up on them.
+const BATCH_GET_RETRIES: usize = 3;
 
 async fn get_item(
     client: &Client,
@@ -139,6 +149,58 @@ pub async fn create_post(client: &Client, table_name: &str, post: &BlogPost) ->
     Ok(())
 }
 
+pub async fn get_post(
+    client: &Client,
+    table_name: &str,
+    pk: &str,
+) -> Result<Option<BlogPost>, String> {
+    match get_item(client, table_name, pk).await?.item {
+        Some(item) => from_item(item).map_err(|e| format!("Deserialization error: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Creates or replaces a post's `META` item.
+pub async fn put_post(client: &Client, table_name: &str, post: &BlogPost) -> Result<(), String> {
+    let item = to_item(post).map_err(|e| format!("Serialization error: {}", e))?;
+    put_item(client, table_name, item).await
+}
+
+/// Deletes a post's `META` item and its comments. Comments go first, so a failure
+/// part way leaves the post in place for the delete to be retried.
+pub async fn delete_post(client: &Client, table_name: &str, pk: &str) -> Result<(), String> {
+    let mut pages = client
+        .query()
+        .table_name(table_name)
+        .key_condition_expression("PK = :pk")
+        .expression_attribute_values(":pk", AttributeValue::S(pk.to_string()))
+        .projection_expression("PK, SK")
+        .into_paginator()
+        .send();
+    let mut sort_keys = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| format!("DynamoDB error: {}", e))?;
+        for item in page.items.unwrap_or_default() {
+            if let Some(Ok(sk)) = item.get("SK").map(AttributeValue::as_s) {
+                sort_keys.push(sk.clone());
+            }
+        }
+    }
+    sort_keys.sort_by_key(|sk| sk == "META");
+
+    for sk in sort_keys {
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(pk.to_string()))
+            .key("SK", AttributeValue::S(sk))
+            .send()
+            .await
+            .map_err(|e| format!("DynamoDB error: {}", e))?;
+    }
+    Ok(())
+}
+
 pub async fn create_content(
     client: &Client,
     table_name: &str,
@@ -163,6 +225,87 @@ pub async fn create_content(
     Ok(())
 }
 
+/// Marks a pending upload as ready at its final `location`. Returns `false` if the item
+/// is not a pending upload owned by `author_id`.
+pub async fn mark_content_ready(
+    client: &Client,
+    table_name: &str,
+    pk: &str,
+    author_id: &str,
+    size: i64,
+    location: &str,
+) -> Result<bool, String> {
+    let status = |status: ContentStatus| {
+        serde_dynamo::to_attribute_value(status).map_err(|e| format!("Serialization error: {}", e))
+    };
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk.to_string()))
+        .key("SK", AttributeValue::S("META".to_string()))
+        .update_expression("SET #status = :ready, #size = :size, #location = :location")
+        .condition_expression("#status = :pending AND authorId = :author")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_names("#size", "size")
+        .expression_attribute_names("#location", "location")
+        .expression_attribute_values(":ready", status(ContentStatus::Ready)?)
+        .expression_attribute_values(":pending", status(ContentStatus::Pending)?)
+        .expression_attribute_values(":author", AttributeValue::S(author_id.to_string()))
+        .expression_attribute_values(":size", AttributeValue::N(size.to_string()))
+        .expression_attribute_values(":location", AttributeValue::S(location.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(format!("DynamoDB error: {}", e)),
+    }
+}
+
+/// Records the renditions of an image and the size of its stored object. Returns
+/// `false` if the content item no longer exists.
+pub async fn set_content_variants(
+    client: &Client,
+    table_name: &str,
+    pk: &str,
+    variants: &[ContentVariant],
+    size: i64,
+) -> Result<bool, String> {
+    let variants = serde_dynamo::to_attribute_value(variants)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk.to_string()))
+        .key("SK", AttributeValue::S("META".to_string()))
+        .update_expression("SET variants = :variants, #size = :size")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_names("#size", "size")
+        .expression_attribute_values(":variants", variants)
+        .expression_attribute_values(":size", AttributeValue::N(size.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(format!("DynamoDB error: {}", e)),
+    }
+}
+
 pub async fn get_content(
     client: &Client,
     table_name: &str,
@@ -175,6 +318,330 @@ pub async fn get_content(
     from_item(item.unwrap()).map_err(|e| format!("Deserialization error: {}", e))
 }
 
+pub async fn get_content_hash(
+    client: &Client,
+    table_name: &str,
+    sha256: &str,
+    author_id: &str,
+) -> Result<Option<ContentHash>, String> {
+    let item = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("HASH#{}", sha256)))
+        .key("SK", AttributeValue::S(format!("AUTHOR#{}", author_id)))
+        .send()
+        .await
+        .map_err(|e| format!("DynamoDB error: {}", e))?
+        .item;
+    match item {
+        Some(item) => from_item(item).map_err(|e| format!("Deserialization error: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Creates or replaces a digest's entry for one author.
+pub async fn put_content_hash(
+    client: &Client,
+    table_name: &str,
+    hash: &ContentHash,
+) -> Result<(), String> {
+    let item = to_item(hash).map_err(|e| format!("Serialization error: {}", e))?;
+    put_item(client, table_name, item).await
+}
+
+/// Records that ready content was uploaded again at `uploaded_at`. Returns false when the
+/// item is gone or no longer ready, in which case the bytes should be stored anew.
+pub async fn touch_content(
+    client: &Client,
+    table_name: &str,
+    pk: &str,
+    uploaded_at: &str,
+) -> Result<bool, String> {
+    let ready = serde_dynamo::to_attribute_value(ContentStatus::Ready)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk.to_string()))
+        .key("SK", AttributeValue::S("META".to_string()))
+        .update_expression("SET lastUploadedAt = :uploaded")
+        .condition_expression("#status = :ready")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":ready", ready)
+        .expression_attribute_values(":uploaded", AttributeValue::S(uploaded_at.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(format!("DynamoDB error: {}", e)),
+    }
+}
+
+/// Removes a content item and its digest index entry, unless the item was uploaded
+/// again since `content` was read. Returns false when it was kept. The object is left
+/// to the caller.
+pub async fn delete_content(
+    client: &Client,
+    table_name: &str,
+    content: &Content,
+) -> Result<bool, String> {
+    let mut delete = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(content.pk.clone()))
+        .key("SK", AttributeValue::S(content.sk.clone()));
+    delete = match &content.last_uploaded_at {
+        Some(uploaded_at) => delete
+            .condition_expression("lastUploadedAt = :seen")
+            .expression_attribute_values(":seen", AttributeValue::S(uploaded_at.clone())),
+        None => delete.condition_expression("attribute_not_exists(lastUploadedAt)"),
+    };
+    match delete.send().await {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+        {
+            return Ok(false);
+        }
+        Err(e) => return Err(format!("DynamoDB error: {}", e)),
+    }
+
+    if let Some(sha256) = &content.sha256 {
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(format!("HASH#{}", sha256)))
+            .key(
+                "SK",
+                AttributeValue::S(format!("AUTHOR#{}", content.author_id)),
+            )
+            .send()
+            .await
+            .map_err(|e| format!("DynamoDB error: {}", e))?;
+    }
+    Ok(true)
+}
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Reads every `META` item whose partition key starts with `prefix`, limited to
+/// `attributes` (plus `PK`) when given. This scans the whole table, so it is only meant
+/// for background jobs.
+async fn scan_meta_items(
+    client: &Client,
+    table_name: &str,
+    prefix: &str,
+    attributes: Option<&[&str]>,
+) -> Result<Vec<Item>, String> {
+    let mut scan = client
+        .scan()
+        .table_name(table_name)
+        .filter_expression("begins_with(#pk, :prefix) AND #sk = :meta")
+        .expression_attribute_names("#pk", "PK")
+        .expression_attribute_names("#sk", "SK")
+        .expression_attribute_values(":prefix", AttributeValue::S(prefix.to_string()))
+        .expression_attribute_values(":meta", AttributeValue::S("META".to_string()));
+    if let Some(attributes) = attributes {
+        let mut projection = vec!["#pk".to_string()];
+        for (i, attribute) in attributes.iter().enumerate() {
+            let name = format!("#a{}", i);
+            scan = scan.expression_attribute_names(&name, *attribute);
+            projection.push(name);
+        }
+        scan = scan.projection_expression(projection.join(", "));
+    }
+
+    let mut pages = scan.into_paginator().send();
+    let mut items = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| format!("DynamoDB error: {}", e))?;
+        items.extend(page.items.unwrap_or_default());
+    }
+    Ok(items)
+}
+
+fn item_pk(item: &Item) -> &str {
+    item.get("PK")
+        .and_then(|pk| pk.as_s().ok())
+        .map_or("?", String::as_str)
+}
+
+/// A string attribute of a scanned item. Any other type is an error, so that callers
+/// deciding what to delete never mistake a malformed item for an empty one.
+fn string_attribute(item: &Item, attribute: &str) -> Result<Option<String>, String> {
+    match item.get(attribute) {
+        None | Some(AttributeValue::Null(_)) => Ok(None),
+        Some(AttributeValue::S(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(format!(
+            "{} of {} is not a string",
+            attribute,
+            item_pk(item)
+        )),
+    }
+}
+
+/// Every content item. Fails if any item does not deserialize.
+pub async fn scan_contents(client: &Client, table_name: &str) -> Result<Vec<Content>, String> {
+    scan_meta_items(client, table_name, "CONTENT#", None)
+        .await?
+        .into_iter()
+        .map(|item| {
+            let pk = item_pk(&item).to_string();
+            from_item(item).map_err(|e| format!("Deserialization error in {}: {}", pk, e))
+        })
+        .collect()
+}
+
+/// The `contentKey` of every post. Fails if a post has none.
+pub async fn scan_post_content_keys(
+    client: &Client,
+    table_name: &str,
+) -> Result<Vec<String>, String> {
+    scan_meta_items(client, table_name, "POST#", Some(&["contentKey"]))
+        .await?
+        .iter()
+        .map(|item| {
+            string_attribute(item, "contentKey")?
+                .ok_or_else(|| format!("{} has no contentKey", item_pk(item)))
+        })
+        .collect()
+}
+
+/// The `avatarContentId` of every author profile that has one.
+pub async fn scan_avatar_content_ids(
+    client: &Client,
+    table_name: &str,
+) -> Result<Vec<String>, String> {
+    let items = scan_meta_items(client, table_name, "AUTHOR#", Some(&["avatarContentId"])).await?;
+    let mut ids = Vec::new();
+    for item in &items {
+        ids.extend(string_attribute(item, "avatarContentId")?);
+    }
+    Ok(ids)
+}
+
+pub async fn get_user(
+    client: &Client,
+    table_name: &str,
+    sub: &str,
+) -> Result<Option<User>, String> {
+    let item = get_item(client, table_name, &format!("USER#{}", sub))
+        .await?
+        .item;
+    match item {
+        Some(item) => from_item(item).map_err(|e| format!("Deserialization error: {}", e)),
+        None => Ok(None),
+    }
+}
+
+pub async fn get_author_profile(
+    client: &Client,
+    table_name: &str,
+    author_id: &str,
+) -> Result<Option<AuthorProfile>, String> {
+    let item = get_item(client, table_name, &format!("AUTHOR#{}", author_id))
+        .await?
+        .item;
+    match item {
+        Some(item) => from_item(item).map_err(|e| format!("Deserialization error: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Creates or replaces an author's profile.
+pub async fn put_author_profile(
+    client: &Client,
+    table_name: &str,
+    profile: &AuthorProfile,
+) -> Result<(), String> {
+    let item = to_item(profile).map_err(|e| format!("Serialization error: {}", e))?;
+    put_item(client, table_name, item).await
+}
+
+/// Looks up the profiles of several authors at once, keyed by author id. Authors
+/// without a profile are left out.
+pub async fn fetch_author_summaries(
+    client: &Client,
+    table_name: &str,
+    author_ids: &[String],
+) -> Result<HashMap<String, AuthorSummary>, String> {
+    let mut unique_ids: Vec<&String> = author_ids.iter().collect();
+    unique_ids.sort();
+    unique_ids.dedup();
+
+    let mut summaries = HashMap::new();
+
+    for chunk in unique_ids.chunks(BATCH_GET_LIMIT) {
+        let keys = chunk
+            .iter()
+            .map(|author_id| {
+                HashMap::from([
+                    (
+                        "PK".to_string(),
+                        AttributeValue::S(format!("AUTHOR#{}", author_id)),
+                    ),
+                    ("SK".to_string(), AttributeValue::S("META".to_string())),
+                ])
+            })
+            .collect();
+        let mut request = KeysAndAttributes::builder()
+            .set_keys(Some(keys))
+            .build()
+            .map_err(|e| format!("DynamoDB request construction failed: {}", e))?;
+
+        for attempt in 0..=BATCH_GET_RETRIES {
+            let output = client
+                .batch_get_item()
+                .request_items(table_name, request)
+                .send()
+                .await
+                .map_err(|e| format!("DynamoDB error: {}", e))?;
+
+            let items = output
+                .responses
+                .and_then(|mut responses| responses.remove(table_name))
+                .unwrap_or_default();
+            for item in items {
+                match from_item::<_, AuthorProfile>(item) {
+                    Ok(profile) => {
+                        summaries.insert(profile.author_id.clone(), profile.into());
+                    }
+                    Err(e) => info!("Failed to deserialize AuthorProfile: {:?}", e),
+                }
+            }
+
+            match output
+                .unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(table_name))
+            {
+                Some(unprocessed) if attempt < BATCH_GET_RETRIES => {
+                    tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                    request = unprocessed;
+                }
+                Some(unprocessed) => {
+                    warn!(
+                        "Giving up on {} unprocessed author profiles",
+                        unprocessed.keys.len()
+                    );
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+
+    Ok(summaries)
+}
+
 pub fn parse_next_token(token: &str) -> Option<HashMap<String, AttributeValue>> {
     let parts: Vec<&str> = token.split('|').collect();
     if parts.len() != 2 {
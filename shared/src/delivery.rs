@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+fn default_inline_max_bytes() -> i64 {
+    1024 * 1024
+}
+
+fn default_inline_content_types() -> Vec<String> {
+    ["text/*", "application/json"]
+        .iter()
+        .map(|t| t.to_string())
+        .collect()
+}
+
+fn default_url_ttl() -> u64 {
+    300
+}
+
+/// How get-content hands out stored objects.
+///
+/// By default every object is returned inline in the response body. With `redirect`
+/// enabled, only small objects of the inline types are, and everything else gets a
+/// 302 to a short-lived presigned S3 URL.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeliveryConfig {
+    #[serde(default)]
+    pub redirect: bool,
+    #[serde(default = "default_inline_max_bytes")]
+    pub inline_max_bytes: i64,
+    /// Exact types or `type/*` wildcards.
+    #[serde(default = "default_inline_content_types")]
+    pub inline_content_types: Vec<String>,
+    /// Seconds a presigned download URL stays valid.
+    #[serde(default = "default_url_ttl")]
+    pub url_ttl: u64,
+    /// Base URL of the API, used for links to content in rendered markdown.
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        DeliveryConfig {
+            redirect: false,
+            inline_max_bytes: default_inline_max_bytes(),
+            inline_content_types: default_inline_content_types(),
+            url_ttl: default_url_ttl(),
+            public_url: None,
+        }
+    }
+}
+
+impl DeliveryConfig {
+    /// The URL get-content serves `content_id` at. Without `public_url` this is a
+    /// root-relative path.
+    pub fn content_url(&self, content_id: &str) -> String {
+        let base = self.public_url.as_deref().unwrap_or_default();
+        format!("{}/content/{}", base.trim_end_matches('/'), content_id)
+    }
+
+    /// Whether an object should be returned in the response body rather than
+    /// redirected to. `size` is `None` when it is not known yet.
+    pub fn is_inline(&self, content_type: &str, size: Option<i64>) -> bool {
+        if !self.redirect {
+            return true;
+        }
+
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let type_allowed =
+            self.inline_content_types
+                .iter()
+                .any(|allowed| match allowed.strip_suffix("/*") {
+                    Some(top_level) => essence
+                        .split_once('/')
+                        .is_some_and(|(t, _)| t.eq_ignore_ascii_case(top_level)),
+                    None => essence.eq_ignore_ascii_case(allowed),
+                });
+
+        type_allowed && size.is_some_and(|size| size <= self.inline_max_bytes)
+    }
+}
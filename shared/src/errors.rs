@@ -1,3 +1,7 @@
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::header::CONTENT_TYPE;
+use aws_lambda_events::http::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,3 +16,18 @@ impl ApiErrorResponse {
         }
     }
 }
+
+/// Builds a JSON error response with an `ApiErrorResponse` body.
+pub fn error_response(status_code: i64, message: &str) -> ApiGatewayProxyResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    let body = serde_json::to_string(&ApiErrorResponse::new(message)).unwrap_or_default();
+
+    ApiGatewayProxyResponse {
+        status_code,
+        headers,
+        body: Some(Body::Text(body)),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,159 @@
+use crate::models::deserialize_string_list;
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+
+/// The YAML header of a markdown post, between `---` lines.
+#[derive(Debug, Deserialize)]
+pub struct FrontMatter {
+    pub title: String,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub tags: Vec<String>,
+    /// `YYYY-MM-DD` or RFC 3339; becomes the post's `createdAt`.
+    pub date: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    /// The id of an existing post to update instead of creating one.
+    pub post: Option<String>,
+}
+
+impl FrontMatter {
+    /// `date` as RFC 3339, or `None` when it is not set.
+    pub fn created_at(&self) -> Result<Option<String>, String> {
+        let Some(date) = self.date.as_deref().map(str::trim) else {
+            return Ok(None);
+        };
+        if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+            return Ok(Some(date.to_rfc3339()));
+        }
+        match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Ok(Some(
+                date.and_hms_opt(0, 0, 0).unwrap().and_utc().to_rfc3339(),
+            )),
+            Err(_) => Err(format!("Invalid front matter date: {:?}", date)),
+        }
+    }
+
+    /// The value of the posts table's `published` attribute.
+    pub fn published(&self) -> String {
+        (!self.draft).to_string()
+    }
+}
+
+fn is_delimiter(line: &str, delimiters: &[&str]) -> bool {
+    delimiters.contains(&line.trim_end())
+}
+
+/// Splits a markdown document into its front matter, if it starts with one, and the
+/// body that follows it.
+///
+/// A leading `---` is also how markdown writes a horizontal rule, so the header only
+/// counts as front matter when a closing `---` (or `...`) follows and the lines between
+/// are a YAML mapping. Otherwise the whole document is the body. Front matter that is a
+/// mapping but not a valid header, e.g. without a `title`, is an error.
+pub fn split_front_matter(markdown: &str) -> Result<(Option<FrontMatter>, &str), String> {
+    let document = markdown.strip_prefix('\u{feff}').unwrap_or(markdown);
+    let Some((first, mut rest)) = document.split_once('\n') else {
+        return Ok((None, markdown));
+    };
+    if !is_delimiter(first, &["---"]) {
+        return Ok((None, markdown));
+    }
+
+    let header = rest;
+    let mut header_len = 0;
+    loop {
+        let (line, remaining) = rest.split_once('\n').unwrap_or((rest, ""));
+        if is_delimiter(line, &["---", "..."]) {
+            let Ok(value @ serde_yaml::Value::Mapping(_)) =
+                serde_yaml::from_str(&header[..header_len])
+            else {
+                return Ok((None, markdown));
+            };
+            let front_matter = serde_yaml::from_value(value)
+                .map_err(|e| format!("Invalid front matter: {}", e))?;
+            return Ok((Some(front_matter), remaining));
+        }
+        if remaining.is_empty() {
+            return Ok((None, markdown));
+        }
+        header_len += line.len() + 1;
+        rest = remaining;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_yaml_header_from_the_body() {
+        let cases = [
+            "---\ntitle: Hello\ntags: [a, b]\n---\n# Body\n",
+            "\u{feff}---\r\ntitle: Hello\r\ntags: a, b\r\n---\r\n# Body\n",
+            "---\ntitle: Hello\ntags: \"[a b]\"\n...\n# Body\n",
+        ];
+        for markdown in cases {
+            let (front_matter, body) = split_front_matter(markdown).unwrap();
+            let front_matter = front_matter.unwrap();
+            assert_eq!(front_matter.title, "Hello");
+            assert_eq!(front_matter.tags, vec!["a", "b"], "{:?}", markdown);
+            assert_eq!(body, "# Body\n");
+        }
+    }
+
+    #[test]
+    fn closing_delimiter_may_end_the_document() {
+        let (front_matter, body) = split_front_matter("---\ntitle: Only\n---").unwrap();
+        assert_eq!(front_matter.unwrap().title, "Only");
+        assert_eq!(body, "");
+    }
+
+    #[test]
+    fn leaves_documents_without_front_matter_alone() {
+        let cases = [
+            "# Title\n\n---\n\ntitle: not front matter\n---\n",
+            // A horizontal rule with no closing delimiter.
+            "---\n\nSome text\n",
+            // A rule, a paragraph and a setext heading underline.
+            "---\nSome text\n---\n",
+            // Only a YAML comment between the rules.
+            "---\n# Heading\n---\n",
+            "---\n---\n",
+            "---\n- a list\n- not a mapping\n---\n",
+            "---\ntitle: [unclosed\n---\n",
+            "---",
+            "",
+        ];
+        for markdown in cases {
+            let (front_matter, body) = split_front_matter(markdown).unwrap();
+            assert!(front_matter.is_none(), "{:?}", markdown);
+            assert_eq!(body, markdown);
+        }
+    }
+
+    #[test]
+    fn rejects_mappings_that_are_not_a_valid_header() {
+        for markdown in [
+            "---\ntags: [a]\n---\nbody",
+            "---\ntitle: Hello\ndraft: maybe\n---\nbody",
+        ] {
+            assert!(split_front_matter(markdown).is_err(), "{:?}", markdown);
+        }
+    }
+
+    #[test]
+    fn reads_dates_and_drafts() {
+        let (front_matter, _) =
+            split_front_matter("---\ntitle: T\ndate: 2024-03-01\ndraft: true\n---\n").unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(
+            front_matter.created_at().unwrap().as_deref(),
+            Some("2024-03-01T00:00:00+00:00")
+        );
+        assert_eq!(front_matter.published(), "false");
+
+        let (front_matter, _) =
+            split_front_matter("---\ntitle: T\ndate: yesterday\n---\n").unwrap();
+        assert!(front_matter.unwrap().created_at().is_err());
+    }
+}
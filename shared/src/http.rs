@@ -0,0 +1,413 @@
+use crate::api::authenticate_bearer;
+use crate::context::AppContext;
+use aws_lambda_events::alb::{AlbTargetGroupRequest, AlbTargetGroupResponse};
+use aws_lambda_events::apigw::{
+    ApiGatewayProxyRequest, ApiGatewayProxyResponse, ApiGatewayV2httpRequest,
+    ApiGatewayV2httpResponse,
+};
+use aws_lambda_events::http::header::{ACCEPT, ORIGIN};
+use aws_lambda_events::http::{HeaderMap, Method};
+use aws_lambda_events::query_map::QueryMap;
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use base64::Engine as _;
+use lambda_runtime::LambdaEvent;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use tracing::info;
+
+/// Which integration delivered the request, so the response can be shaped to match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// API Gateway REST API proxy integration (payload format 1.0).
+    RestApi,
+    /// API Gateway HTTP API (payload format 2.0).
+    HttpApi,
+    /// Application Load Balancer target group.
+    Alb { multi_value_headers: bool },
+}
+
+/// Any of the HTTP events a handler can be wired to.
+///
+/// HTTP API events are identified by `"version": "2.0"` and ALB events by
+/// `requestContext.elb`; everything else is treated as a REST API event. Our sample
+/// payloads carry a `routeKey` without a version, so that field alone is not enough.
+#[derive(Debug, Clone)]
+pub enum HttpEvent {
+    RestApi(Box<ApiGatewayProxyRequest>),
+    HttpApi(Box<ApiGatewayV2httpRequest>),
+    Alb(Box<AlbTargetGroupRequest>),
+}
+
+impl<'de> Deserialize<'de> for HttpEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        let event = if value.get("version").and_then(Value::as_str) == Some("2.0") {
+            HttpEvent::HttpApi(Box::new(
+                serde_json::from_value(value).map_err(serde::de::Error::custom)?,
+            ))
+        } else if value.pointer("/requestContext/elb").is_some() {
+            HttpEvent::Alb(Box::new(
+                serde_json::from_value(value).map_err(serde::de::Error::custom)?,
+            ))
+        } else {
+            HttpEvent::RestApi(Box::new(
+                serde_json::from_value(value).map_err(serde::de::Error::custom)?,
+            ))
+        };
+
+        Ok(event)
+    }
+}
+
+impl HttpEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            HttpEvent::RestApi(_) => EventKind::RestApi,
+            HttpEvent::HttpApi(_) => EventKind::HttpApi,
+            HttpEvent::Alb(request) => EventKind::Alb {
+                multi_value_headers: !request.multi_value_headers.is_empty(),
+            },
+        }
+    }
+}
+
+/// An HTTP request normalized across REST API, HTTP API and ALB events.
+#[derive(Debug, Clone, Default)]
+pub struct ApiRequest {
+    pub http_method: Method,
+    pub path: Option<String>,
+    /// The matched resource template (e.g. `/post/{id}`), when the integration provides one.
+    pub resource: Option<String>,
+    pub path_parameters: HashMap<String, String>,
+    pub query_string_parameters: QueryMap,
+    pub headers: HeaderMap,
+    /// The request body, already decoded when the integration base64-encoded text.
+    pub body: Option<String>,
+    pub is_base64_encoded: bool,
+    /// Token claims from whichever authorizer fronted the request.
+    pub claims: Option<Map<String, Value>>,
+    pub source_ip: Option<String>,
+    pub stage: Option<String>,
+}
+
+impl ApiRequest {
+    /// Whether the `Accept` header explicitly lists `content_type` with a non-zero
+    /// quality. Wildcards do not count, so clients only get a different representation
+    /// when they ask for it.
+    pub fn accepts(&self, content_type: &str) -> bool {
+        let Some(accept) = self.headers.get(ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return false;
+        };
+        accept.split(',').any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            params
+                .next()
+                .is_some_and(|t| t.eq_ignore_ascii_case(content_type))
+                && !params.any(|p| {
+                    p.strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                })
+        })
+    }
+}
+
+impl From<ApiGatewayProxyRequest> for ApiRequest {
+    fn from(request: ApiGatewayProxyRequest) -> Self {
+        // Cognito user pool authorizers put the token claims under `claims`.
+        let claims = request
+            .request_context
+            .authorizer
+            .fields
+            .get("claims")
+            .and_then(Value::as_object)
+            .cloned();
+
+        let (body, is_base64_encoded) = decode_text_body(request.body, request.is_base64_encoded);
+
+        ApiRequest {
+            http_method: request.http_method,
+            path: request.path,
+            resource: request.resource,
+            path_parameters: request.path_parameters,
+            query_string_parameters: request.query_string_parameters,
+            headers: request.headers,
+            body,
+            is_base64_encoded,
+            claims,
+            source_ip: request.request_context.identity.source_ip,
+            stage: request.request_context.stage,
+        }
+    }
+}
+
+impl From<ApiGatewayV2httpRequest> for ApiRequest {
+    fn from(request: ApiGatewayV2httpRequest) -> Self {
+        let context = request.request_context;
+
+        // JWT authorizers expose claims as a flat string map; Lambda authorizers return
+        // whatever context they were given, which we expect to hold `claims` as well.
+        let claims = context.authorizer.as_ref().and_then(|authorizer| {
+            if let Some(jwt) = &authorizer.jwt {
+                Some(
+                    jwt.claims
+                        .iter()
+                        .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                        .collect(),
+                )
+            } else {
+                authorizer
+                    .fields
+                    .get("claims")
+                    .and_then(Value::as_object)
+                    .cloned()
+            }
+        });
+
+        // The route key looks like "GET /post/{id}", or "$default" for the catch-all route.
+        let resource = request
+            .route_key
+            .as_deref()
+            .and_then(|key| key.split_once(' '))
+            .map(|(_, resource)| resource.to_string());
+
+        // `rawPath` starts with the stage name on every stage except `$default`.
+        let path =
+            request
+                .raw_path
+                .or(context.http.path)
+                .map(|path| match context.stage.as_deref() {
+                    Some(stage) if stage != "$default" => strip_stage(path, stage),
+                    _ => path,
+                });
+
+        let (body, is_base64_encoded) = decode_text_body(request.body, request.is_base64_encoded);
+
+        ApiRequest {
+            http_method: context.http.method,
+            path,
+            resource,
+            path_parameters: request.path_parameters,
+            query_string_parameters: request.query_string_parameters,
+            headers: request.headers,
+            body,
+            is_base64_encoded,
+            claims,
+            source_ip: context.http.source_ip,
+            stage: context.stage,
+        }
+    }
+}
+
+impl From<AlbTargetGroupRequest> for ApiRequest {
+    fn from(request: AlbTargetGroupRequest) -> Self {
+        let headers = if request.headers.is_empty() {
+            request.multi_value_headers
+        } else {
+            request.headers
+        };
+
+        let source_ip = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(|v| v.trim().to_string());
+
+        // ALB forwards query string values exactly as the client sent them.
+        let query_string_parameters = if request.multi_value_query_string_parameters.is_empty() {
+            request.query_string_parameters
+        } else {
+            request.multi_value_query_string_parameters
+        };
+        let mut decoded_query: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, value) in query_string_parameters.iter() {
+            decoded_query
+                .entry(percent_decode(key))
+                .or_default()
+                .push(percent_decode(value));
+        }
+
+        let (body, is_base64_encoded) = decode_text_body(request.body, request.is_base64_encoded);
+
+        ApiRequest {
+            http_method: request.http_method,
+            path: request.path,
+            resource: None,
+            path_parameters: HashMap::new(),
+            query_string_parameters: decoded_query.into(),
+            headers,
+            body,
+            is_base64_encoded,
+            // Clients can send the `x-amzn-oidc-*` headers themselves, so they carry no
+            // claims until `authenticate_bearer` has verified the access token.
+            claims: None,
+            source_ip,
+            stage: None,
+        }
+    }
+}
+
+impl From<HttpEvent> for ApiRequest {
+    fn from(event: HttpEvent) -> Self {
+        match event {
+            HttpEvent::RestApi(request) => (*request).into(),
+            HttpEvent::HttpApi(request) => (*request).into(),
+            HttpEvent::Alb(request) => (*request).into(),
+        }
+    }
+}
+
+/// A handler response in the shape the originating integration expects.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum HttpResponse {
+    RestApi(ApiGatewayProxyResponse),
+    HttpApi(ApiGatewayV2httpResponse),
+    Alb(AlbTargetGroupResponse),
+}
+
+impl HttpResponse {
+    pub fn new(kind: EventKind, response: ApiGatewayProxyResponse) -> Self {
+        match kind {
+            EventKind::RestApi => HttpResponse::RestApi(response),
+            EventKind::HttpApi => HttpResponse::HttpApi(ApiGatewayV2httpResponse {
+                status_code: response.status_code,
+                headers: response.headers,
+                multi_value_headers: response.multi_value_headers,
+                body: response.body,
+                is_base64_encoded: response.is_base64_encoded,
+                cookies: Vec::new(),
+            }),
+            EventKind::Alb {
+                multi_value_headers,
+            } => {
+                let mut headers = response.headers;
+                headers.extend(response.multi_value_headers);
+                let (headers, multi_value_headers) = if multi_value_headers {
+                    (HeaderMap::new(), headers)
+                } else {
+                    (headers, HeaderMap::new())
+                };
+                HttpResponse::Alb(AlbTargetGroupResponse {
+                    status_code: response.status_code,
+                    status_description: Some(status_description(response.status_code)),
+                    headers,
+                    multi_value_headers,
+                    body: response.body,
+                    is_base64_encoded: response.is_base64_encoded,
+                })
+            }
+        }
+    }
+}
+
+/// Normalizes an incoming HTTP event, runs `handler` on it and converts the response
+/// back into the integration's format.
+///
+/// CORS preflight requests are answered here without reaching the handler, and every
+/// other response gets the CORS headers for the request's origin. When the context has
+/// a JWT verifier, bearer tokens are verified here so handlers see the same claims
+/// behind an ALB as behind an API Gateway authorizer. A token that fails verification
+/// leaves the request anonymous; handlers that require a scope then answer 401.
+pub async fn handle_http_event<F, Fut, E>(
+    ctx: &AppContext,
+    event: LambdaEvent<HttpEvent>,
+    handler: F,
+) -> Result<HttpResponse, E>
+where
+    F: FnOnce(LambdaEvent<ApiRequest>) -> Fut,
+    Fut: Future<Output = Result<ApiGatewayProxyResponse, E>>,
+{
+    let (payload, context) = event.into_parts();
+    let kind = payload.kind();
+    let mut request: ApiRequest = payload.into();
+    let cors = &ctx.config.cors;
+
+    if let Some(response) = cors.preflight_response(&request) {
+        return Ok(HttpResponse::new(kind, response));
+    }
+
+    let origin = request
+        .headers
+        .get(ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    if let Some(verifier) = &ctx.jwt_verifier {
+        if authenticate_bearer(verifier, &mut request).await.is_err() {
+            info!("Handling request with an invalid bearer token as anonymous");
+        }
+    }
+
+    let mut response = handler(LambdaEvent::new(request, context)).await?;
+    cors.apply(origin.as_deref(), &mut response.headers);
+
+    Ok(HttpResponse::new(kind, response))
+}
+
+fn status_description(status_code: i64) -> String {
+    let reason = u16::try_from(status_code)
+        .ok()
+        .and_then(|code| aws_lambda_events::http::StatusCode::from_u16(code).ok())
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("");
+    format!("{} {}", status_code, reason).trim_end().to_string()
+}
+
+/// Integrations base64-encode bodies they consider binary; handlers parse JSON text,
+/// so decode the body whenever the result is valid UTF-8.
+fn decode_text_body(body: Option<String>, is_base64_encoded: bool) -> (Option<String>, bool) {
+    match body {
+        Some(encoded) if is_base64_encoded => {
+            match base64_engine
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+            {
+                Some(text) => (Some(text), false),
+                None => (Some(encoded), true),
+            }
+        }
+        body => (body, false),
+    }
+}
+
+fn strip_stage(path: String, stage: &str) -> String {
+    match path
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_prefix(stage))
+    {
+        Some("") => "/".to_string(),
+        Some(rest) if rest.starts_with('/') => rest.to_string(),
+        _ => path,
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,140 @@
+use crate::models::ContentVariant;
+use serde::Deserialize;
+
+fn default_widths() -> Vec<u32> {
+    vec![320, 640, 1280]
+}
+
+fn default_webp() -> bool {
+    true
+}
+
+/// Which renditions process-image produces for each uploaded image.
+///
+/// Every image gets a full-size copy with its metadata stripped, plus one per width
+/// that is smaller than the original, in the original format and, with `webp`, as WebP
+/// where that is smaller.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageConfig {
+    #[serde(default = "default_widths")]
+    pub widths: Vec<u32>,
+    #[serde(default = "default_webp")]
+    pub webp: bool,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig {
+            widths: default_widths(),
+            webp: default_webp(),
+        }
+    }
+}
+
+/// Picks the rendition get-content serves for a request.
+///
+/// With a requested `width`, the smallest rendition in the original format at least
+/// that wide is chosen, or the largest if none is; without one, the full-size rendition
+/// is. When `webp` is set (the client accepts it), a WebP rendition of the same width
+/// is served instead if it is smaller. Returns `None` when the image has not been
+/// processed.
+pub fn select_variant<'a>(
+    variants: &'a [ContentVariant],
+    original_type: &str,
+    width: Option<u32>,
+    webp: bool,
+) -> Option<&'a ContentVariant> {
+    let of_type = |content_type: &str| -> Vec<&'a ContentVariant> {
+        variants
+            .iter()
+            .filter(|v| v.content_type.eq_ignore_ascii_case(content_type))
+            .collect()
+    };
+
+    let mut candidates = of_type(original_type);
+    if candidates.is_empty() && webp {
+        candidates = of_type("image/webp");
+    }
+
+    let largest = candidates.iter().copied().max_by_key(|v| v.width);
+    let chosen = match width {
+        Some(width) => candidates
+            .iter()
+            .copied()
+            .filter(|v| v.width >= width)
+            .min_by_key(|v| v.width)
+            .or(largest),
+        None => largest,
+    }?;
+
+    if !webp {
+        return Some(chosen);
+    }
+    let smaller_webp = of_type("image/webp")
+        .into_iter()
+        .find(|v| v.width == chosen.width && v.size < chosen.size);
+    Some(smaller_webp.unwrap_or(chosen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(content_type: &str, width: u32, size: i64) -> ContentVariant {
+        let extension = content_type.trim_start_matches("image/");
+        ContentVariant {
+            key: format!("variants/id/w{}.{}", width, extension),
+            content_type: content_type.to_string(),
+            width,
+            height: width / 2,
+            size,
+        }
+    }
+
+    fn variants() -> Vec<ContentVariant> {
+        vec![
+            variant("image/png", 320, 1000),
+            variant("image/png", 640, 2000),
+            variant("image/png", 1280, 4000),
+            variant("image/webp", 320, 500),
+            variant("image/webp", 640, 2500),
+        ]
+    }
+
+    fn selected(width: Option<u32>, webp: bool) -> Option<String> {
+        select_variant(&variants(), "image/png", width, webp).map(|v| v.key.clone())
+    }
+
+    #[test]
+    fn picks_the_smallest_rendition_at_least_as_wide() {
+        assert_eq!(selected(None, false).unwrap(), "variants/id/w1280.png");
+        assert_eq!(selected(Some(320), false).unwrap(), "variants/id/w320.png");
+        assert_eq!(selected(Some(500), false).unwrap(), "variants/id/w640.png");
+        assert_eq!(
+            selected(Some(5000), false).unwrap(),
+            "variants/id/w1280.png"
+        );
+    }
+
+    #[test]
+    fn prefers_webp_only_when_it_is_smaller() {
+        assert_eq!(selected(Some(300), true).unwrap(), "variants/id/w320.webp");
+        assert_eq!(selected(Some(640), true).unwrap(), "variants/id/w640.png");
+        // No WebP rendition at full size.
+        assert_eq!(selected(None, true).unwrap(), "variants/id/w1280.png");
+    }
+
+    #[test]
+    fn webp_originals_and_unprocessed_images() {
+        let webp_only = vec![
+            variant("image/webp", 320, 500),
+            variant("image/webp", 640, 900),
+        ];
+        let chosen = select_variant(&webp_only, "image/webp", Some(400), true).unwrap();
+        assert_eq!(chosen.width, 640);
+        let chosen = select_variant(&webp_only, "image/webp", Some(400), false).unwrap();
+        assert_eq!(chosen.width, 640);
+
+        assert!(select_variant(&[], "image/png", None, true).is_none());
+    }
+}
@@ -0,0 +1,494 @@
+use crate::models::Claims;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long fetched keys are trusted before the JWKS is downloaded again.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+/// Minimum gap between refreshes triggered by an unknown `kid`, so tokens with made-up
+/// key ids cannot make us hammer the JWKS endpoint.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum JwtError {
+    Malformed(String),
+    UnknownKey(String),
+    Invalid(String),
+    Jwks(String),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::Malformed(e) => write!(f, "malformed token: {}", e),
+            JwtError::UnknownKey(kid) => write!(f, "no signing key with kid {}", kid),
+            JwtError::Invalid(e) => write!(f, "invalid token: {}", e),
+            JwtError::Jwks(e) => write!(f, "could not load JWKS: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+#[derive(Debug, Clone)]
+pub enum JwksSource {
+    Url(String),
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// `client_id` claim on the guest tokens guest-jwt-generator signs itself.
+pub const GUEST_CLIENT_ID: &str = "blog-guest";
+
+/// One issuer whose tokens are accepted, with the keys they are checked against.
+#[derive(Debug)]
+struct TrustedIssuer {
+    issuer: String,
+    client_ids: Vec<String>,
+    algorithms: Vec<Algorithm>,
+    source: JwksSource,
+    cache: RwLock<Option<CachedJwks>>,
+}
+
+impl TrustedIssuer {
+    fn new(
+        issuer: &str,
+        client_ids: Vec<String>,
+        algorithms: Vec<Algorithm>,
+        source: JwksSource,
+    ) -> Self {
+        TrustedIssuer {
+            issuer: issuer.to_string(),
+            client_ids,
+            algorithms,
+            source,
+            cache: RwLock::new(None),
+        }
+    }
+
+    fn decoding_key(&self, kid: &str) -> Result<Option<DecodingKey>, JwtError> {
+        let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+        match cache.as_ref().and_then(|cached| cached.keys.find(kid)) {
+            Some(jwk) => DecodingKey::from_jwk(jwk)
+                .map(Some)
+                .map_err(|e| JwtError::Jwks(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Verifies Cognito user pool tokens, and optionally self-signed guest tokens, without
+/// an API Gateway authorizer in front.
+#[derive(Debug)]
+pub struct JwtVerifier {
+    issuers: Vec<TrustedIssuer>,
+    http: reqwest::Client,
+}
+
+impl JwtVerifier {
+    /// Accepts RS256 tokens from `issuer`, as Cognito signs them.
+    pub fn new(issuer: &str, client_ids: Vec<String>, source: JwksSource) -> Self {
+        JwtVerifier {
+            issuers: vec![TrustedIssuer::new(
+                issuer,
+                client_ids,
+                vec![Algorithm::RS256],
+                source,
+            )],
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Verifies tokens issued by a Cognito user pool, fetching its published JWKS.
+    pub fn for_user_pool(region: &str, user_pool_id: &str, client_ids: Vec<String>) -> Self {
+        let issuer = format!(
+            "https://cognito-idp.{}.amazonaws.com/{}",
+            region, user_pool_id
+        );
+        let jwks_url = format!("{}/.well-known/jwks.json", issuer);
+        Self::new(&issuer, client_ids, JwksSource::Url(jwks_url))
+    }
+
+    /// Verifies only the guest tokens guest-jwt-generator signs with its own key.
+    pub fn for_guest_issuer(issuer: &str, source: JwksSource) -> Self {
+        JwtVerifier {
+            issuers: Vec::new(),
+            http: reqwest::Client::new(),
+        }
+        .with_guest_issuer(issuer, source)
+    }
+
+    /// Replaces where the keys of the first issuer are loaded from.
+    pub fn with_jwks_source(mut self, source: JwksSource) -> Self {
+        if let Some(trusted) = self.issuers.first_mut() {
+            trusted.source = source;
+        }
+        self
+    }
+
+    /// Also accepts guest tokens signed by guest-jwt-generator, which uses RS256 or
+    /// ES256 depending on its key.
+    pub fn with_guest_issuer(mut self, issuer: &str, source: JwksSource) -> Self {
+        self.issuers.push(TrustedIssuer::new(
+            issuer,
+            vec![GUEST_CLIENT_ID.to_string()],
+            vec![Algorithm::RS256, Algorithm::ES256],
+            source,
+        ));
+        self
+    }
+
+    /// Verifies a token and returns its claims in the same shape an API Gateway
+    /// authorizer would pass on, so they can be placed on an `ApiRequest`.
+    pub async fn verify_claims(&self, token: &str) -> Result<Map<String, Value>, JwtError> {
+        let header = decode_header(token).map_err(|e| JwtError::Malformed(e.to_string()))?;
+        let kid = header
+            .kid
+            .ok_or_else(|| JwtError::Malformed("missing kid".to_string()))?;
+
+        // The issuer picks the keys, so it is read before the signature is checked;
+        // `decode` below verifies it again.
+        let issuer = unverified_issuer(token)?;
+        let trusted = self
+            .issuers
+            .iter()
+            .find(|trusted| trusted.issuer == issuer)
+            .ok_or_else(|| JwtError::Invalid(format!("untrusted issuer {}", issuer)))?;
+        if !trusted.algorithms.contains(&header.alg) {
+            return Err(JwtError::Invalid(format!(
+                "unexpected algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        self.refresh(trusted, false).await?;
+
+        let key = match trusted.decoding_key(&kid)? {
+            Some(key) => key,
+            None => {
+                // Cognito rotates keys occasionally; refresh once before giving up.
+                self.refresh(trusted, true).await?;
+                trusted
+                    .decoding_key(&kid)?
+                    .ok_or_else(|| JwtError::UnknownKey(kid.clone()))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&trusted.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        // ID tokens carry the app client in `aud`, access tokens in `client_id`; both
+        // are checked below.
+        validation.validate_aud = false;
+
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| JwtError::Invalid(e.to_string()))?
+            .claims;
+
+        let client_claim = match claims.get("token_use").and_then(Value::as_str) {
+            Some("access") => "client_id",
+            Some("id") => "aud",
+            other => {
+                return Err(JwtError::Invalid(format!(
+                    "unexpected token_use {:?}",
+                    other
+                )))
+            }
+        };
+        let client_id = claims
+            .get(client_claim)
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !trusted.client_ids.iter().any(|id| id == client_id) {
+            return Err(JwtError::Invalid(format!(
+                "token issued for unexpected client {}",
+                client_id
+            )));
+        }
+
+        Ok(claims)
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Claims, JwtError> {
+        let claims = self.verify_claims(token).await?;
+        serde_json::from_value(Value::Object(claims)).map_err(|e| JwtError::Invalid(e.to_string()))
+    }
+
+    async fn refresh(&self, trusted: &TrustedIssuer, force: bool) -> Result<(), JwtError> {
+        {
+            let cache = trusted.cache.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(cached) = cache.as_ref() {
+                let age = cached.fetched_at.elapsed();
+                if age < JWKS_MIN_REFRESH || (!force && age < JWKS_TTL) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let keys: JwkSet = match &trusted.source {
+            JwksSource::Url(url) => {
+                info!("Fetching JWKS from {}", url);
+                self.http
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| JwtError::Jwks(e.to_string()))?
+                    .json()
+                    .await
+                    .map_err(|e| JwtError::Jwks(e.to_string()))?
+            }
+            JwksSource::File(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| JwtError::Jwks(format!("{}: {}", path.display(), e)))?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| JwtError::Jwks(format!("{}: {}", path.display(), e)))?
+            }
+        };
+
+        if keys.keys.is_empty() {
+            warn!("JWKS from {:?} contains no keys", trusted.source);
+        }
+
+        *trusted.cache.write().unwrap_or_else(|e| e.into_inner()) = Some(CachedJwks {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+}
+
+/// The `iss` claim of a token whose signature has not been checked yet.
+fn unverified_issuer(token: &str) -> Result<String, JwtError> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| JwtError::Malformed("missing payload".to_string()))?;
+    let bytes = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|e| JwtError::Malformed(e.to_string()))?;
+    let claims: Map<String, Value> =
+        serde_json::from_slice(&bytes).map_err(|e| JwtError::Malformed(e.to_string()))?;
+    claims
+        .get("iss")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| JwtError::Malformed("missing iss".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::path::Path;
+
+    const ISSUER: &str = "https://cognito-idp.us-east-1.amazonaws.com/us-east-1_test";
+    const GUEST_ISSUER: &str = "https://guest.example.com";
+    const CLIENT: &str = "app-client";
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    fn sign(key: &str, alg: Algorithm, kid: &str, claims: &Value) -> String {
+        let pem = std::fs::read(testdata(key)).unwrap();
+        let key = match alg {
+            Algorithm::ES256 => EncodingKey::from_ec_pem(&pem),
+            _ => EncodingKey::from_rsa_pem(&pem),
+        }
+        .unwrap();
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.to_string());
+        encode(&header, claims, &key).unwrap()
+    }
+
+    fn claims(token_use: &str) -> Value {
+        let mut claims = json!({
+            "iss": ISSUER,
+            "sub": "user",
+            "token_use": token_use,
+            "exp": chrono::Utc::now().timestamp() + 600,
+        });
+        let client_claim = if token_use == "id" {
+            "aud"
+        } else {
+            "client_id"
+        };
+        claims[client_claim] = json!(CLIENT);
+        claims
+    }
+
+    fn verifier() -> JwtVerifier {
+        JwtVerifier::new(
+            ISSUER,
+            vec![CLIENT.to_string()],
+            JwksSource::File(testdata("rsa.jwks.json")),
+        )
+    }
+
+    async fn verify(
+        verifier: &JwtVerifier,
+        claims: &Value,
+    ) -> Result<Map<String, Value>, JwtError> {
+        let token = sign("rsa.pem", Algorithm::RS256, "test-rsa", claims);
+        verifier.verify_claims(&token).await
+    }
+
+    #[tokio::test]
+    async fn accepts_access_and_id_tokens() {
+        let verifier = verifier();
+        for token_use in ["access", "id"] {
+            let claims = verify(&verifier, &claims(token_use)).await.unwrap();
+            assert_eq!(claims["sub"], "user", "{}", token_use);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_a_signature_from_another_key() {
+        let token = sign(
+            "rsa_other.pem",
+            Algorithm::RS256,
+            "test-rsa",
+            &claims("access"),
+        );
+        let result = verifier().verify_claims(&token).await;
+        assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_issuers() {
+        let mut claims = claims("access");
+        claims["iss"] = json!("https://cognito-idp.us-east-1.amazonaws.com/us-east-1_other");
+        let result = verify(&verifier(), &claims).await;
+        assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn checks_the_client_claim_that_matches_token_use() {
+        // An access token's client is in `client_id`; `aud` does not count.
+        let mut access = claims("access");
+        access["client_id"] = json!("other-client");
+        access["aud"] = json!(CLIENT);
+        // An ID token's client is in `aud`; `client_id` does not count.
+        let mut id = claims("id");
+        id["aud"] = json!("other-client");
+        id["client_id"] = json!(CLIENT);
+
+        let verifier = verifier();
+        for claims in [access, id] {
+            let result = verify(&verifier, &claims).await;
+            assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_token_use() {
+        let verifier = verifier();
+        let mut missing = claims("access");
+        missing.as_object_mut().unwrap().remove("token_use");
+        for claims in [claims("refresh"), missing] {
+            let result = verify(&verifier, &claims).await;
+            assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_tokens() {
+        let mut claims = claims("access");
+        claims["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        let result = verify(&verifier(), &claims).await;
+        assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_without_a_known_kid() {
+        let verifier = verifier();
+        let token = sign("rsa.pem", Algorithm::RS256, "unknown", &claims("access"));
+        let result = verifier.verify_claims(&token).await;
+        assert!(
+            matches!(result, Err(JwtError::UnknownKey(_))),
+            "{:?}",
+            result
+        );
+
+        let token = encode(
+            &Header::new(Algorithm::RS256),
+            &claims("access"),
+            &EncodingKey::from_rsa_pem(&std::fs::read(testdata("rsa.pem")).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let result = verifier.verify_claims(&token).await;
+        assert!(
+            matches!(result, Err(JwtError::Malformed(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_kid_refreshes_at_most_once_a_minute() {
+        let path = std::env::temp_dir().join(format!("jwks-refresh-{}.json", std::process::id()));
+        std::fs::copy(testdata("rsa.jwks.json"), &path).unwrap();
+        let verifier = JwtVerifier::new(
+            ISSUER,
+            vec![CLIENT.to_string()],
+            JwksSource::File(path.clone()),
+        );
+        verify(&verifier, &claims("access")).await.unwrap();
+
+        // The keys rotate, but the last fetch was too recent to fetch them again.
+        std::fs::copy(testdata("rsa_other.jwks.json"), &path).unwrap();
+        let rotated = sign(
+            "rsa_other.pem",
+            Algorithm::RS256,
+            "other-rsa",
+            &claims("access"),
+        );
+        let result = verifier.verify_claims(&rotated).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            matches!(result, Err(JwtError::UnknownKey(_))),
+            "{:?}",
+            result
+        );
+        verify(&verifier, &claims("access")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn accepts_guest_tokens_from_the_guest_issuer_only() {
+        let verifier =
+            verifier().with_guest_issuer(GUEST_ISSUER, JwksSource::File(testdata("ec.jwks.json")));
+        let mut guest = claims("access");
+        guest["iss"] = json!(GUEST_ISSUER);
+        guest["client_id"] = json!(GUEST_CLIENT_ID);
+        let token = sign("ec.pem", Algorithm::ES256, "test-ec", &guest);
+        assert!(verifier.verify_claims(&token).await.is_ok());
+
+        // The user pool's clients are not valid for guest tokens.
+        guest["client_id"] = json!(CLIENT);
+        let token = sign("ec.pem", Algorithm::ES256, "test-ec", &guest);
+        let result = verifier.verify_claims(&token).await;
+        assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+
+        // Cognito only signs with RS256.
+        let token = sign("ec.pem", Algorithm::ES256, "test-ec", &claims("access"));
+        let result = verifier.verify_claims(&token).await;
+        assert!(matches!(result, Err(JwtError::Invalid(_))), "{:?}", result);
+    }
+}
@@ -1,4 +1,14 @@
 pub mod api;
+pub mod config;
+pub mod context;
+pub mod cors;
 pub mod db;
+pub mod delivery;
 pub mod errors;
+pub mod front_matter;
+pub mod http;
+pub mod images;
+pub mod jwt;
+pub mod markdown;
+pub mod media;
 pub mod models;
@@ -0,0 +1,472 @@
+use crate::models::{PostMetadata, TocEntry};
+use ammonia::Builder;
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Prefix for element ids in rendered HTML, so they cannot clash with the page's own.
+const ID_PREFIX: &str = "user-content-";
+
+const WORDS_PER_MINUTE: u32 = 200;
+const EXCERPT_CHARS: usize = 200;
+
+const ALLOWED_CLASSES: &[&str] = &[
+    "footnote-reference",
+    "footnote-definition",
+    "footnote-definition-label",
+];
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// The id in a `content/<id>` reference, with or without a leading `/` or extension.
+fn content_reference(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix('/')
+        .unwrap_or(url)
+        .strip_prefix("content/")?;
+    let id = rest.split_once('.').map_or(rest, |(id, _)| id);
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')).then_some(id)
+}
+
+fn filter_attribute<'a>(element: &str, attribute: &str, value: &'a str) -> Option<Cow<'a, str>> {
+    match (element, attribute) {
+        (_, "class") => {
+            let classes: Vec<&str> = value
+                .split_whitespace()
+                .filter(|class| {
+                    ALLOWED_CLASSES.contains(class)
+                        || (element == "code"
+                            && class.strip_prefix("language-").is_some_and(|language| {
+                                language
+                                    .chars()
+                                    .all(|c| c.is_ascii_alphanumeric() || "+-_#.".contains(c))
+                            }))
+                })
+                .collect();
+            (!classes.is_empty()).then(|| classes.join(" ").into())
+        }
+        (_, "id") => Some(format!("{}{}", ID_PREFIX, value).into()),
+        ("a", "href") => match value.strip_prefix('#') {
+            Some(fragment) => Some(format!("#{}{}", ID_PREFIX, fragment).into()),
+            None => Some(value.into()),
+        },
+        ("th" | "td", "style") => matches!(
+            value,
+            "text-align: left" | "text-align: center" | "text-align: right"
+        )
+        .then(|| value.into()),
+        ("input", "type") => (value == "checkbox").then(|| value.into()),
+        _ => Some(value.into()),
+    }
+}
+
+struct Heading {
+    /// Position of the heading's `Start` event.
+    index: usize,
+    level: HeadingLevel,
+    text: String,
+    slug: String,
+}
+
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c);
+        } else if c.is_whitespace() {
+            slug.push('-');
+        }
+    }
+    slug
+}
+
+/// Finds the headings in a parsed document and gives each a unique slug, numbering
+/// repeats (`intro`, `intro-1`, ...) as GitHub does.
+fn headings(events: &[Event]) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<(usize, HeadingLevel, String)> = None;
+
+    for (index, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((index, *level, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, heading)) = &mut current {
+                    heading.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let Some((index, level, text)) = current.take() else {
+                    continue;
+                };
+                let mut slug = slugify(&text);
+                if slug.is_empty() {
+                    slug = "section".to_string();
+                }
+                let repeats = seen.entry(slug.clone()).or_default();
+                if *repeats > 0 {
+                    slug = format!("{}-{}", slug, repeats);
+                }
+                *repeats += 1;
+                headings.push(Heading {
+                    index,
+                    level,
+                    text: text.trim().to_string(),
+                    slug,
+                });
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("th", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .add_generic_attributes(["class", "id"])
+        .attribute_filter(filter_attribute);
+    builder
+});
+
+/// Renders CommonMark with the GitHub extensions (tables, footnotes, strikethrough and
+/// task lists) to HTML that is safe to embed in a page.
+///
+/// Raw HTML in the source is kept only where the sanitizer's allowlist permits, and
+/// images that reference `content/<id>` are pointed at `content_url(id)`. Headings get
+/// the ids listed in the post's table of contents.
+pub fn render_html(markdown: &str, content_url: impl Fn(&str) -> String) -> String {
+    let parser = Parser::new_ext(markdown, parser_options());
+    let mut events: Vec<Event> = parser
+        .map(|event| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let dest_url = match content_reference(&dest_url) {
+                    Some(content_id) => CowStr::from(content_url(content_id)),
+                    None => dest_url,
+                };
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            event => event,
+        })
+        .collect();
+
+    for heading in headings(&events) {
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[heading.index] {
+            *id = Some(heading.slug.into());
+        }
+    }
+
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, events.into_iter());
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+/// The ids of content that images and links in `markdown` point at, including absolute
+/// URLs of get-content.
+pub fn content_references(markdown: &str) -> Vec<String> {
+    Parser::new_ext(markdown, parser_options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. } | Tag::Link { dest_url, .. }) => {
+                let path = dest_url.split(['?', '#']).next().unwrap_or_default();
+                let start = path.find("content/")?;
+                content_reference(&path[start..]).map(str::to_string)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Shortens `text` to at most `EXCERPT_CHARS` characters, at a word boundary.
+fn truncate_excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= EXCERPT_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(EXCERPT_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// Word count, reading time, excerpt and table of contents for a post's markdown.
+///
+/// Code blocks are left out of the word count and excerpt; the excerpt is taken from
+/// the leading paragraphs.
+pub fn post_metadata(markdown: &str) -> PostMetadata {
+    let events: Vec<Event> = Parser::new_ext(markdown, parser_options()).collect();
+
+    // Text is collected before counting, so inline markup such as `*em*phasis` or
+    // `` `code`. `` does not split or add words.
+    let mut text = String::new();
+    let mut excerpt = String::new();
+    let mut in_code_block = false;
+    let mut in_paragraph = false;
+    for event in &events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(TagEnd::Paragraph) => {
+                in_paragraph = false;
+                excerpt.push(' ');
+                text.push(' ');
+            }
+            Event::End(
+                TagEnd::Heading(_) | TagEnd::Item | TagEnd::TableCell | TagEnd::FootnoteDefinition,
+            ) => text.push(' '),
+            Event::Text(chunk) | Event::Code(chunk) if !in_code_block => {
+                text.push_str(chunk);
+                if in_paragraph && excerpt.len() <= EXCERPT_CHARS * 4 {
+                    excerpt.push_str(chunk);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                text.push(' ');
+                if in_paragraph {
+                    excerpt.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    let word_count = text.split_whitespace().count() as u32;
+
+    let toc = headings(&events)
+        .into_iter()
+        .map(|heading| TocEntry {
+            level: heading.level as u8,
+            text: heading.text,
+            anchor: format!("{}{}", ID_PREFIX, heading.slug),
+        })
+        .collect();
+
+    PostMetadata {
+        word_count,
+        reading_time_minutes: word_count.div_ceil(WORDS_PER_MINUTE),
+        excerpt: truncate_excerpt(&excerpt),
+        toc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        render_html(markdown, |id| format!("https://cdn.example.com/{}", id))
+    }
+
+    #[test]
+    fn strips_scripts_and_javascript_urls() {
+        let html = render(
+            "<script>alert(1)</script>\n\n[click](javascript:alert(2)) <a href=\"javascript:alert(3)\" onclick=\"alert(4)\">raw</a>",
+        );
+        assert!(!html.contains("script"), "{}", html);
+        assert!(!html.contains("javascript:"), "{}", html);
+        assert!(!html.contains("onclick"), "{}", html);
+        assert!(html.contains(">click</a>"), "{}", html);
+    }
+
+    #[test]
+    fn keeps_only_allowlisted_classes() {
+        let html = render("```rust\nfn main() {}\n```\n\n<span class=\"language-rust evil\">x</span> <code class=\"language-c++ evil\">y</code> <code class=\"language-a\\\"b\">z</code>");
+        assert!(
+            html.contains("<pre><code class=\"language-rust\">"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<span>x</span>"), "{}", html);
+        assert!(
+            html.contains("<code class=\"language-c++\">y</code>"),
+            "{}",
+            html
+        );
+        assert!(!html.contains("evil"), "{}", html);
+
+        let footnote = render("Text[^1]\n\n[^1]: Note");
+        assert!(
+            footnote.contains("class=\"footnote-reference\""),
+            "{}",
+            footnote
+        );
+        assert!(
+            footnote.contains("class=\"footnote-definition\""),
+            "{}",
+            footnote
+        );
+    }
+
+    #[test]
+    fn prefixes_ids_and_fragment_links() {
+        let html =
+            render("<div id=\"main\">x</div>\n\n[jump](#main) [away](https://example.com/#top)");
+        assert!(html.contains("<div id=\"user-content-main\">"), "{}", html);
+        assert!(html.contains("href=\"#user-content-main\""), "{}", html);
+        assert!(
+            html.contains("href=\"https://example.com/#top\""),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn points_content_images_at_content_urls() {
+        let html = render(
+            "![a](content/abc-123.png) ![b](/content/def) ![c](https://example.com/content/ghi.png) ![d](content/../x.png)",
+        );
+        assert!(
+            html.contains("src=\"https://cdn.example.com/abc-123\""),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("src=\"https://cdn.example.com/def\""),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("src=\"https://example.com/content/ghi.png\""),
+            "{}",
+            html
+        );
+        assert!(html.contains("src=\"content/../x.png\""), "{}", html);
+    }
+
+    #[test]
+    fn renders_tables_and_task_lists() {
+        let html = render("| a | b |\n|:-|-:|\n| 1 | 2 |\n\n- [x] done\n- [ ] todo");
+        assert!(
+            html.contains("<th style=\"text-align: left\">a</th>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<td style=\"text-align: right\">2</td>"),
+            "{}",
+            html
+        );
+        assert!(html.contains("type=\"checkbox\""), "{}", html);
+    }
+
+    #[test]
+    fn heading_ids_are_unique_slugs() {
+        let html =
+            render("# Hello, World!\n\n## Intro\n\n## Intro\n\n## !!!\n\n[back](#hello-world)");
+        assert!(
+            html.contains("<h1 id=\"user-content-hello-world\">"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<h2 id=\"user-content-intro\">"), "{}", html);
+        assert!(
+            html.contains("<h2 id=\"user-content-intro-1\">"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<h2 id=\"user-content-section\">"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("href=\"#user-content-hello-world\""),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn toc_matches_the_rendered_heading_ids() {
+        let metadata =
+            post_metadata("# Title\n\n## Setup `cargo`\n\n### Setup cargo\n\n## Setup `cargo`");
+        let toc: Vec<(u8, &str, &str)> = metadata
+            .toc
+            .iter()
+            .map(|entry| (entry.level, entry.text.as_str(), entry.anchor.as_str()))
+            .collect();
+        assert_eq!(
+            toc,
+            vec![
+                (1, "Title", "user-content-title"),
+                (2, "Setup cargo", "user-content-setup-cargo"),
+                (3, "Setup cargo", "user-content-setup-cargo-1"),
+                (2, "Setup cargo", "user-content-setup-cargo-2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_words_outside_code_blocks() {
+        let metadata =
+            post_metadata("# Two words\n\nThree more `words`.\n\n```\nnot counted at all\n```\n");
+        assert_eq!(metadata.word_count, 5);
+        assert_eq!(metadata.reading_time_minutes, 1);
+    }
+
+    #[test]
+    fn reading_time_rounds_up() {
+        let words = |count: usize| vec!["word"; count].join(" ");
+        assert_eq!(post_metadata("").reading_time_minutes, 0);
+        assert_eq!(post_metadata(&words(200)).reading_time_minutes, 1);
+        assert_eq!(post_metadata(&words(201)).reading_time_minutes, 2);
+        assert_eq!(post_metadata(&words(401)).word_count, 401);
+        assert_eq!(post_metadata(&words(401)).reading_time_minutes, 3);
+    }
+
+    #[test]
+    fn excerpt_comes_from_paragraphs() {
+        let metadata =
+            post_metadata("# Heading\n\nFirst *line*\nwrapped.\n\n```\ncode\n```\n\nSecond.");
+        assert_eq!(metadata.excerpt, "First line wrapped. Second.");
+    }
+
+    #[test]
+    fn excerpt_is_cut_at_a_word_boundary() {
+        let text = "lorem ipsum, ".repeat(30);
+        let excerpt = post_metadata(&text).excerpt;
+        assert!(
+            excerpt.ends_with("ipsum…") || excerpt.ends_with("lorem…"),
+            "{}",
+            excerpt
+        );
+        assert!(excerpt.chars().count() <= EXCERPT_CHARS + 1);
+        assert!(!excerpt.contains(",…"));
+    }
+
+    #[test]
+    fn excerpt_is_cut_on_a_char_boundary() {
+        let text = "é".repeat(EXCERPT_CHARS + 50);
+        let excerpt = post_metadata(&text).excerpt;
+        assert_eq!(excerpt, format!("{}…", "é".repeat(EXCERPT_CHARS)));
+
+        let short = "ünïcödé ".repeat(10);
+        assert_eq!(post_metadata(&short).excerpt, short.trim_end());
+    }
+}
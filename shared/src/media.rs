@@ -0,0 +1,315 @@
+use crate::api::AuthenticatedUser;
+use crate::errors::error_response;
+use aws_lambda_events::apigw::ApiGatewayProxyResponse;
+
+const KIB: usize = 1024;
+const MIB: usize = 1024 * KIB;
+
+/// Bytes needed from the start of a file to check its signature.
+pub const SNIFF_LEN: usize = 512;
+
+/// Where presigned uploads land until finalize-upload copies them to `content/`.
+pub const UPLOAD_PREFIX: &str = "uploads/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signature {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Pdf,
+    Mp4,
+    /// UTF-8 without NUL bytes.
+    Text,
+}
+
+impl Signature {
+    fn matches(self, head: &[u8]) -> bool {
+        match self {
+            Signature::Png => head.starts_with(b"\x89PNG\r\n\x1a\n"),
+            Signature::Jpeg => head.starts_with(b"\xff\xd8\xff"),
+            Signature::Gif => head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a"),
+            Signature::Webp => head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP"),
+            Signature::Pdf => head.starts_with(b"%PDF-"),
+            Signature::Mp4 => head.get(4..8) == Some(b"ftyp"),
+            Signature::Text => {
+                !head.contains(&0)
+                    && match std::str::from_utf8(head) {
+                        Ok(_) => true,
+                        // `head` may end partway through a character.
+                        Err(e) => e.error_len().is_none(),
+                    }
+            }
+        }
+    }
+}
+
+/// A content type the API accepts, and what an upload of it must look like.
+#[derive(Debug)]
+pub struct MediaType {
+    pub content_type: &'static str,
+    /// Accepted file extensions; the first is used for the S3 key.
+    pub extensions: &'static [&'static str],
+    /// Largest file of this type, before the endpoint's own limit is applied.
+    pub max_bytes: usize,
+    /// Any one of these scopes may upload this type.
+    pub scopes: &'static [&'static str],
+    signature: Signature,
+}
+
+const AUTHOR_SCOPES: &[&str] = &["author.write", "admin.write"];
+const ADMIN_SCOPES: &[&str] = &["admin.write"];
+
+pub const MEDIA_TYPES: &[MediaType] = &[
+    MediaType {
+        content_type: "text/markdown",
+        extensions: &["md", "markdown"],
+        max_bytes: MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Text,
+    },
+    MediaType {
+        content_type: "text/plain",
+        extensions: &["txt"],
+        max_bytes: MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Text,
+    },
+    MediaType {
+        content_type: "image/png",
+        extensions: &["png"],
+        max_bytes: 10 * MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Png,
+    },
+    MediaType {
+        content_type: "image/jpeg",
+        extensions: &["jpg", "jpeg"],
+        max_bytes: 10 * MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Jpeg,
+    },
+    MediaType {
+        content_type: "image/gif",
+        extensions: &["gif"],
+        max_bytes: 10 * MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Gif,
+    },
+    MediaType {
+        content_type: "image/webp",
+        extensions: &["webp"],
+        max_bytes: 10 * MIB,
+        scopes: AUTHOR_SCOPES,
+        signature: Signature::Webp,
+    },
+    MediaType {
+        content_type: "application/pdf",
+        extensions: &["pdf"],
+        max_bytes: 50 * MIB,
+        scopes: ADMIN_SCOPES,
+        signature: Signature::Pdf,
+    },
+    MediaType {
+        content_type: "video/mp4",
+        extensions: &["mp4"],
+        max_bytes: 500 * MIB,
+        scopes: ADMIN_SCOPES,
+        signature: Signature::Mp4,
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaError {
+    /// Not on the allowlist, or not allowed for the caller's scopes.
+    UnsupportedType(String),
+    /// The extension does not belong to the declared type.
+    ExtensionMismatch(String),
+    /// The file's leading bytes do not match the declared type.
+    SignatureMismatch(&'static str),
+    TooLarge(usize),
+}
+
+impl MediaError {
+    pub fn into_response(self) -> ApiGatewayProxyResponse {
+        match self {
+            MediaError::UnsupportedType(content_type) => error_response(
+                415,
+                &format!("Content type {} is not allowed", content_type),
+            ),
+            MediaError::ExtensionMismatch(extension) => error_response(
+                415,
+                &format!(
+                    "File extension {:?} does not match the content type",
+                    extension
+                ),
+            ),
+            MediaError::SignatureMismatch(content_type) => {
+                error_response(415, &format!("File contents are not {}", content_type))
+            }
+            MediaError::TooLarge(max_bytes) => error_response(
+                413,
+                &format!(
+                    "Content too large; the limit for this type is {} bytes",
+                    max_bytes
+                ),
+            ),
+        }
+    }
+}
+
+impl MediaType {
+    /// Looks up an allowlisted type, ignoring parameters such as `charset`.
+    pub fn find(content_type: &str) -> Option<&'static MediaType> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        MEDIA_TYPES
+            .iter()
+            .find(|media_type| media_type.content_type.eq_ignore_ascii_case(essence))
+    }
+
+    /// Finds the allowlist entry for a declared upload, checking that `user` may upload
+    /// it, that `extension` belongs to it and that `size` is within its limit, clamped
+    /// to `endpoint_limit`.
+    pub fn for_upload(
+        user: &AuthenticatedUser,
+        content_type: &str,
+        extension: &str,
+        size: usize,
+        endpoint_limit: usize,
+    ) -> Result<&'static MediaType, MediaError> {
+        let media_type = MediaType::find(content_type)
+            .filter(|media_type| media_type.scopes.iter().any(|s| user.has_scope(s)))
+            .ok_or_else(|| MediaError::UnsupportedType(content_type.trim().to_string()))?;
+
+        let extension = extension.to_ascii_lowercase();
+        if !media_type.extensions.contains(&extension.as_str()) {
+            return Err(MediaError::ExtensionMismatch(extension));
+        }
+
+        let max_bytes = media_type.max_bytes.min(endpoint_limit);
+        if size > max_bytes {
+            return Err(MediaError::TooLarge(max_bytes));
+        }
+
+        Ok(media_type)
+    }
+
+    /// The extension to store the file under, independent of what the client sent.
+    pub fn extension(&self) -> &'static str {
+        self.extensions[0]
+    }
+
+    /// Checks the first bytes of the file (up to `SNIFF_LEN`) against the type.
+    pub fn check_signature(&self, head: &[u8]) -> Result<(), MediaError> {
+        let head = &head[..head.len().min(SNIFF_LEN)];
+        if self.signature.matches(head) {
+            Ok(())
+        } else {
+            Err(MediaError::SignatureMismatch(self.content_type))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Claims;
+
+    const LIMIT: usize = 100 * MIB;
+
+    fn user(scopes: &[&str]) -> AuthenticatedUser {
+        AuthenticatedUser {
+            sub: "user".to_string(),
+            username: "user".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            claims: Claims::default(),
+        }
+    }
+
+    #[test]
+    fn admin_only_types_need_admin_write() {
+        let author = user(&["author.write"]);
+        let admin = user(&["admin.write"]);
+        for (content_type, extension) in [("application/pdf", "pdf"), ("video/mp4", "mp4")] {
+            assert_eq!(
+                MediaType::for_upload(&author, content_type, extension, 1, LIMIT).unwrap_err(),
+                MediaError::UnsupportedType(content_type.to_string())
+            );
+            assert!(MediaType::for_upload(&admin, content_type, extension, 1, LIMIT).is_ok());
+        }
+        assert!(MediaType::for_upload(&author, "image/png", "png", 1, LIMIT).is_ok());
+        assert!(
+            MediaType::for_upload(&user(&["guest.read"]), "image/png", "png", 1, LIMIT).is_err()
+        );
+    }
+
+    #[test]
+    fn type_lookup_ignores_case_and_parameters() {
+        let author = user(&["author.write"]);
+        let media_type =
+            MediaType::for_upload(&author, "Text/Markdown; charset=utf-8", "MD", 1, LIMIT).unwrap();
+        assert_eq!(media_type.content_type, "text/markdown");
+        assert_eq!(media_type.extension(), "md");
+        assert_eq!(
+            MediaType::for_upload(&author, "text/html", "html", 1, LIMIT).unwrap_err(),
+            MediaError::UnsupportedType("text/html".to_string())
+        );
+    }
+
+    #[test]
+    fn extension_must_belong_to_the_type() {
+        let author = user(&["author.write"]);
+        assert!(MediaType::for_upload(&author, "image/jpeg", "jpeg", 1, LIMIT).is_ok());
+        assert_eq!(
+            MediaType::for_upload(&author, "image/jpeg", "png", 1, LIMIT).unwrap_err(),
+            MediaError::ExtensionMismatch("png".to_string())
+        );
+    }
+
+    #[test]
+    fn size_is_limited_by_type_and_endpoint() {
+        let admin = user(&["admin.write"]);
+        assert!(MediaType::for_upload(&admin, "image/png", "png", 10 * MIB, LIMIT).is_ok());
+        assert_eq!(
+            MediaType::for_upload(&admin, "image/png", "png", 10 * MIB + 1, LIMIT).unwrap_err(),
+            MediaError::TooLarge(10 * MIB)
+        );
+        // MP4 allows 500 MiB, but the endpoint allows less.
+        assert_eq!(
+            MediaType::for_upload(&admin, "video/mp4", "mp4", LIMIT + 1, LIMIT).unwrap_err(),
+            MediaError::TooLarge(LIMIT)
+        );
+        assert_eq!(MediaError::TooLarge(LIMIT).into_response().status_code, 413);
+    }
+
+    #[test]
+    fn signature_must_match_the_declared_type() {
+        let png = MediaType::find("image/png").unwrap();
+        assert!(png
+            .check_signature(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")
+            .is_ok());
+
+        let err = png
+            .check_signature(b"<!DOCTYPE html><script>alert(1)</script>")
+            .unwrap_err();
+        assert_eq!(err, MediaError::SignatureMismatch("image/png"));
+        assert_eq!(err.into_response().status_code, 415);
+
+        let webp = MediaType::find("image/webp").unwrap();
+        assert!(webp.check_signature(b"RIFF\x10\0\0\0WEBPVP8L").is_ok());
+        assert!(webp.check_signature(b"RIFF\x10\0\0\0WAVEfmt ").is_err());
+    }
+
+    #[test]
+    fn text_must_be_utf8_without_nul() {
+        let markdown = MediaType::find("text/markdown").unwrap();
+        assert!(markdown.check_signature("# Überschrift".as_bytes()).is_ok());
+        assert!(markdown.check_signature(b"\x89PNG\r\n\x1a\n").is_err());
+        assert!(markdown.check_signature(b"text\0more").is_err());
+
+        // A character cut off by the sniff length is fine.
+        let mut head = vec![b'a'; SNIFF_LEN - 1];
+        head.extend("é".as_bytes());
+        assert!(markdown.check_signature(&head).is_ok());
+    }
+}
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct BlogPost {
@@ -13,8 +16,34 @@ pub struct BlogPost {
     pub published: String,
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    /// The id of the post's content item.
     #[serde(rename = "contentKey")]
     pub content_key: String,
+    /// Derived from the linked markdown whenever the link changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PostMetadata>,
+}
+
+/// Summary information about a post's markdown, for list pages.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PostMetadata {
+    #[serde(rename = "wordCount")]
+    pub word_count: u32,
+    #[serde(rename = "readingTimeMinutes")]
+    pub reading_time_minutes: u32,
+    /// Plain text from the start of the post.
+    pub excerpt: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub toc: Vec<TocEntry>,
+}
+
+/// A heading in a post, in document order.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    /// The heading's element id in HTML rendered by `shared::markdown`.
+    pub anchor: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -30,6 +59,17 @@ pub struct Comment {
     pub created_at: String,
 }
 
+/// Whether a content item's object has been uploaded and checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentStatus {
+    /// Reserved for a presigned upload that has not been finalized yet.
+    Pending,
+    /// Items written before uploads could be pending have no status and are ready.
+    #[default]
+    Ready,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Content {
     #[serde(rename = "PK")]
@@ -41,22 +81,194 @@ pub struct Content {
     pub location: String,
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    /// When create-content last received these bytes again and returned this item.
+    /// Garbage collection measures its grace period from here when it is set.
+    #[serde(
+        rename = "lastUploadedAt",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_uploaded_at: Option<String>,
     #[serde(rename = "authorId")]
     pub author_id: String,
+    #[serde(default)]
+    pub status: ContentStatus,
+    /// Object size in bytes; for pending uploads, the size the upload was signed for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    /// Resized and re-encoded renditions of an image, recorded by process-image.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<ContentVariant>,
+    /// Hex SHA-256 of the bytes create-content received, which process-image may later
+    /// replace with a copy without metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
-pub struct Claims {
+/// Points from a digest to an author's content item with those bytes. Stored in the
+/// content table as `HASH#<sha256>` / `AUTHOR#<sub>`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ContentHash {
+    #[serde(rename = "PK")]
+    pub pk: String,
+    #[serde(rename = "SK")]
+    pub sk: String,
+    #[serde(rename = "contentId")]
+    pub content_id: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// One rendition of an image, stored under `variants/<content id>/`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ContentVariant {
+    pub key: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: i64,
+}
+
+impl Content {
+    /// The object key within `bucket`, parsed from `location`.
+    pub fn s3_key(&self, bucket: &str) -> Option<&str> {
+        self.location.strip_prefix(&format!("s3://{}/", bucket))
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        self.content_type
+            .split(';')
+            .next()
+            .is_some_and(|t| t.trim().eq_ignore_ascii_case("text/markdown"))
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.content_type
+            .trim_start()
+            .get(..6)
+            .is_some_and(|t| t.eq_ignore_ascii_case("image/"))
+    }
+}
+
+/// Public profile of a post author, stored in the posts table as `AUTHOR#<sub>`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorProfile {
+    #[serde(rename = "PK")]
+    pub pk: String,
+    #[serde(rename = "SK")]
+    pub sk: String,
+    #[serde(rename = "authorId")]
+    pub author_id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(default)]
+    pub bio: String,
+    #[serde(
+        rename = "avatarContentId",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub avatar_content_id: Option<String>,
+    /// Link name (e.g. `github`) to URL.
+    #[serde(rename = "socialLinks", default)]
+    pub social_links: BTreeMap<String, String>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// The part of an author's profile embedded in post responses.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthorSummary {
+    #[serde(rename = "authorId")]
+    pub author_id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "avatarContentId", skip_serializing_if = "Option::is_none")]
+    pub avatar_content_id: Option<String>,
+}
+
+impl From<AuthorProfile> for AuthorSummary {
+    fn from(profile: AuthorProfile) -> Self {
+        AuthorSummary {
+            author_id: profile.author_id,
+            display_name: profile.display_name,
+            avatar_content_id: profile.avatar_content_id,
+        }
+    }
+}
+
+/// A post's linked content, embedded in get-post responses on request.
+#[derive(Debug, Serialize)]
+pub struct PostContent {
+    #[serde(rename = "contentId")]
+    pub content_id: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub url: String,
+    /// The content itself, for text small enough to inline, or rendered HTML.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// A post together with its author's summary, if the author has a profile.
+#[derive(Debug, Serialize)]
+pub struct PostWithAuthor {
+    #[serde(flatten)]
+    pub post: BlogPost,
+    pub author: Option<AuthorSummary>,
+}
+
+/// A row in the users table, keyed by `USER#<sub>`, holding what pretoken-generation
+/// copies into tokens.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    #[serde(rename = "PK")]
+    pub pk: String,
+    #[serde(rename = "SK")]
+    pub sk: String,
+    #[serde(rename = "authorId", default)]
+    pub author_id: Option<String>,
+    #[serde(rename = "displayName", default)]
+    pub display_name: Option<String>,
     #[serde(default)]
-    pub auth_time: String,
+    pub roles: Vec<String>,
+    #[serde(rename = "blogId", default)]
+    pub blog_id: Option<String>,
+}
+
+/// Stage whose Cognito groups carry no `+<stage>` suffix.
+pub const PROD_STAGE: &str = "Prod";
+
+/// Token claims as passed on by the API Gateway authorizer or carried in a Cognito JWT.
+///
+/// REST API authorizers stringify every claim (timestamps become either epoch seconds or
+/// dates like `Wed Apr 16 00:00:00 UTC 2025`, groups a comma separated list) while HTTP
+/// API JWT authorizers render arrays as `[a b]`, so the typed fields accept all of those.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct Claims {
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    pub auth_time: Option<DateTime<Utc>>,
     #[serde(default)]
     pub client_id: String,
     #[serde(default)]
     pub event_id: String,
-    #[serde(default)]
-    pub exp: String,
-    #[serde(default)]
-    pub iat: String,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    pub exp: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    pub iat: Option<DateTime<Utc>>,
     #[serde(default)]
     pub iss: String,
     #[serde(default)]
@@ -64,13 +276,171 @@ pub struct Claims {
     #[serde(default)]
     pub origin_jti: String,
     #[serde(default)]
-    pub scope: String,
+    pub scope: Scopes,
     #[serde(default)]
     pub sub: String,
     #[serde(default)]
     pub token_use: String,
     #[serde(default)]
     pub username: String,
+    #[serde(
+        rename = "cognito:groups",
+        default,
+        deserialize_with = "deserialize_string_list"
+    )]
+    pub groups: Vec<String>,
+    /// Added by pretoken-generation from the users table.
+    #[serde(
+        rename = "blog:author_id",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub author_id: Option<String>,
+    #[serde(
+        rename = "blog:author_name",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub author_name: Option<String>,
+    #[serde(
+        rename = "blog:roles",
+        default,
+        deserialize_with = "deserialize_string_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub roles: Vec<String>,
+    #[serde(
+        rename = "blog:blog_id",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub blog_id: Option<String>,
+}
+
+impl Claims {
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.exp.is_some_and(|exp| exp <= now)
+    }
+
+    /// Whether the user belongs to `group` on `stage`. Stages share one user pool, so
+    /// outside `Prod` the Cognito group is named `<group>+<stage>`, and membership in
+    /// another stage's group does not count.
+    pub fn in_group(&self, group: &str, stage: &str) -> bool {
+        let name = if stage == PROD_STAGE {
+            group.to_string()
+        } else {
+            format!("{}+{}", group, stage)
+        };
+        self.groups.contains(&name)
+    }
+
+    pub fn is_admin(&self, stage: &str) -> bool {
+        self.in_group("blog-admin", stage)
+    }
+
+    pub fn is_author(&self, stage: &str) -> bool {
+        self.in_group("blog-author", stage)
+    }
+}
+
+/// The space separated `scope` claim.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scopes(pub BTreeSet<String>);
+
+impl Scopes {
+    /// Scope names without their resource server prefix, e.g. `author.write` for
+    /// `https://api.example.com/author.write`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .map(|scope| scope.rsplit('/').next().unwrap_or(scope))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|scope| scope == name)
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let joined: Vec<&str> = self.0.iter().map(String::as_str).collect();
+        serializer.serialize_str(&joined.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let scopes = match Value::deserialize(deserializer)? {
+            Value::String(s) => s.split_whitespace().map(str::to_string).collect(),
+            Value::Array(values) => values
+                .into_iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect(),
+            Value::Null => BTreeSet::new(),
+            other => return Err(de::Error::custom(format!("invalid scope: {}", other))),
+        };
+        Ok(Scopes(scopes))
+    }
+}
+
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let seconds = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) if s.is_empty() => None,
+        Value::String(s) => match s.parse::<i64>() {
+            Ok(seconds) => Some(seconds),
+            Err(_) => {
+                let date = NaiveDateTime::parse_from_str(&s, "%a %b %d %H:%M:%S UTC %Y")
+                    .map_err(|_| de::Error::custom(format!("invalid timestamp: {}", s)))?;
+                Some(date.and_utc().timestamp())
+            }
+        },
+        Value::Null => None,
+        other => return Err(de::Error::custom(format!("invalid timestamp: {}", other))),
+    };
+    Ok(seconds.and_then(|s| DateTime::from_timestamp(s, 0)))
+}
+
+fn serialize_timestamp<S: Serializer>(
+    value: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(date) => serializer.serialize_i64(date.timestamp()),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn deserialize_string_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let list = match Value::deserialize(deserializer)? {
+        Value::Array(values) => values
+            .into_iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        Value::String(s) => {
+            let s = s.trim();
+            match s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                Some(inner) => inner.split_whitespace().map(str::to_string).collect(),
+                None => s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|g| !g.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            }
+        }
+        Value::Null => Vec::new(),
+        other => return Err(de::Error::custom(format!("invalid list: {}", other))),
+    };
+    Ok(list)
 }
 
 fn default_published() -> String {
@@ -84,6 +454,7 @@ fn default_tags() -> Vec<String> {
 #[derive(Debug, Deserialize)]
 pub struct CreatePostRequest {
     pub title: String,
+    /// The id of a content item, from create-content or create-upload, holding the body.
     #[serde(rename = "contentKey")]
     pub content_key: String,
     #[serde(default = "default_tags")]
@@ -102,6 +473,18 @@ pub struct CreatePostResponse {
     pub author_id: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdateAuthorProfileRequest {
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(default)]
+    pub bio: String,
+    #[serde(rename = "avatarContentId", default)]
+    pub avatar_content_id: Option<String>,
+    #[serde(rename = "socialLinks", default)]
+    pub social_links: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateContentRequest {
     #[serde(rename = "contentType")]
@@ -124,4 +507,147 @@ pub struct CreateContentResponse {
     pub created_at: String,
     #[serde(rename = "authorId")]
     pub author_id: String,
+    pub status: ContentStatus,
+    /// The post created or updated from the markdown's front matter.
+    #[serde(rename = "postId", skip_serializing_if = "Option::is_none")]
+    pub post_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    #[serde(rename = "fileExtension")]
+    pub file_extension: String,
+    /// Exact size of the file in bytes; the upload URL only accepts this length.
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateUploadResponse {
+    #[serde(rename = "contentId")]
+    pub content_id: String,
+    #[serde(rename = "uploadUrl")]
+    pub upload_url: String,
+    pub method: String,
+    /// Headers the client must send with the upload, exactly as given.
+    pub headers: BTreeMap<String, String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(groups: &[&str]) -> Claims {
+        Claims {
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn in_group_matches_the_stage_suffix() {
+        assert!(claims(&["blog-admin"]).is_admin("Prod"));
+        assert!(claims(&["blog-admin+Dev"]).is_admin("Dev"));
+        assert!(claims(&["blog-author+Dev"]).is_author("Dev"));
+    }
+
+    #[test]
+    fn in_group_rejects_other_stages() {
+        assert!(!claims(&["blog-admin+Dev"]).is_admin("Prod"));
+        assert!(!claims(&["blog-admin"]).is_admin("Dev"));
+        assert!(!claims(&["blog-admin+Beta"]).is_admin("Dev"));
+        assert!(!claims(&["blog-admin+Dev+x"]).is_admin("Dev"));
+    }
+
+    fn claims_from(value: Value) -> Claims {
+        serde_json::from_value(value).expect("claims should deserialize")
+    }
+
+    #[test]
+    fn timestamps_accept_numbers_numeric_strings_and_dates() {
+        let claims = claims_from(serde_json::json!({
+            "exp": 1700000000,
+            "iat": "1700000000",
+            "auth_time": "Tue Nov 14 22:13:20 UTC 2023",
+        }));
+        let expected = DateTime::from_timestamp(1700000000, 0);
+        assert_eq!(claims.exp, expected);
+        assert_eq!(claims.iat, expected);
+        assert_eq!(claims.auth_time, expected);
+    }
+
+    #[test]
+    fn timestamps_may_be_empty_or_missing() {
+        let claims = claims_from(serde_json::json!({ "exp": "", "iat": null }));
+        assert_eq!(claims.exp, None);
+        assert_eq!(claims.iat, None);
+        assert_eq!(claims.auth_time, None);
+    }
+
+    #[test]
+    fn timestamps_reject_other_formats() {
+        for exp in [
+            serde_json::json!("2023-11-14T22:13:20Z"),
+            serde_json::json!(true),
+        ] {
+            let result = serde_json::from_value::<Claims>(serde_json::json!({ "exp": exp }));
+            assert!(result.is_err(), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn string_lists_accept_arrays_and_both_string_forms() {
+        let cases = [
+            (
+                serde_json::json!(["blog-admin", "blog-author"]),
+                vec!["blog-admin", "blog-author"],
+            ),
+            (
+                serde_json::json!("[blog-admin blog-author]"),
+                vec!["blog-admin", "blog-author"],
+            ),
+            (
+                serde_json::json!("blog-admin, blog-author,"),
+                vec!["blog-admin", "blog-author"],
+            ),
+            (serde_json::json!("blog-admin"), vec!["blog-admin"]),
+            (serde_json::json!("[]"), vec![]),
+            (serde_json::json!(""), vec![]),
+            (serde_json::json!(null), vec![]),
+        ];
+        for (groups, expected) in cases {
+            let claims = claims_from(serde_json::json!({ "cognito:groups": groups }));
+            assert_eq!(claims.groups, expected, "{}", groups);
+        }
+    }
+
+    #[test]
+    fn content_kind_comes_from_the_media_type() {
+        let cases = [
+            ("image/png", true, false),
+            ("IMAGE/WEBP", true, false),
+            ("image/svg+xml", true, false),
+            ("text/markdown; charset=utf-8", false, true),
+            ("Text/Markdown", false, true),
+            ("application/pdf", false, false),
+            ("imagex/png", false, false),
+            ("", false, false),
+        ];
+        for (content_type, image, markdown) in cases {
+            let content: Content = serde_json::from_value(serde_json::json!({
+                "PK": "CONTENT#id",
+                "SK": "META",
+                "contentType": content_type,
+                "location": "s3://bucket/id",
+                "createdAt": "2024-01-01T00:00:00Z",
+                "authorId": "author",
+            }))
+            .unwrap();
+            assert_eq!(content.is_image(), image, "{}", content_type);
+            assert_eq!(content.is_markdown(), markdown, "{}", content_type);
+        }
+    }
 }